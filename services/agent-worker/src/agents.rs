@@ -0,0 +1,260 @@
+//! Agent Configuration
+//!
+//! Which model server tasks run against, and the model, temperature and
+//! system prompt each agent uses. Loaded from `WORKER_AGENTS_FILE` (YAML, or
+//! TOML by extension); see `agents.example.yaml`. Without a file the worker
+//! talks to Ollama's generate API at `LLM_ENDPOINT` with the built-in agents.
+//!
+//! Agents call no tools unless their `tools` list names them (see
+//! [`crate::tools`]).
+//!
+//! An agent's settings are looked up by its full `agent_id` first, then by
+//! the part before the first `-` (`summarizer-1` -> `summarizer`), then fall
+//! back to `default`.
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::llm::{self, BackendKind, LlmBackend};
+
+/// Model for agents without settings of their own.
+const DEFAULT_MODEL: &str = "llama3.1:8b";
+
+/// Context window, in tokens, for models without a configured one.
+pub const DEFAULT_CONTEXT_TOKENS: usize = 8192;
+
+/// Model server settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    /// Full endpoint URL (default: the kind's local default)
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable holding the API key, for servers that need one
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Stream output as it is generated (default: true)
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+/// Settings for one agent
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentSettings {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Model context window in tokens; session history is cut to fit
+    #[serde(default)]
+    pub context_tokens: Option<usize>,
+    /// Tools the agent may call, by name
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+}
+
+/// The agents file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentsConfig {
+    pub backend: BackendConfig,
+    #[serde(default)]
+    pub default: AgentSettings,
+    #[serde(default)]
+    pub agents: HashMap<String, AgentSettings>,
+}
+
+/// What a task runs with, after lookup and defaults
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedAgent {
+    pub model: String,
+    pub temperature: Option<f32>,
+    pub system_prompt: Option<String>,
+    pub context_tokens: usize,
+    pub tools: Vec<String>,
+}
+
+impl AgentsConfig {
+    /// Load from `WORKER_AGENTS_FILE`, or the built-in configuration using
+    /// `LLM_BACKEND` (default: `ollama_generate`) and `LLM_ENDPOINT`.
+    pub fn from_env() -> Result<Self> {
+        if let Some(path) = std::env::var("WORKER_AGENTS_FILE").ok().filter(|p| !p.is_empty()) {
+            return Self::load(Path::new(&path));
+        }
+
+        let kind = match std::env::var("LLM_BACKEND").ok().filter(|k| !k.is_empty()) {
+            Some(kind) => kind.parse()?,
+            None => BackendKind::OllamaGenerate,
+        };
+        Ok(Self::builtin(kind, std::env::var("LLM_ENDPOINT").ok()))
+    }
+
+    /// The models the worker has always used: `coder` agents on codellama,
+    /// everything else on llama3.1.
+    pub fn builtin(kind: BackendKind, url: Option<String>) -> Self {
+        let model = |name: &str| AgentSettings {
+            model: Some(name.to_string()),
+            ..Default::default()
+        };
+        Self {
+            backend: BackendConfig {
+                kind,
+                url,
+                api_key_env: None,
+                stream: true,
+            },
+            default: model(DEFAULT_MODEL),
+            agents: HashMap::from([
+                ("summarizer".to_string(), model("llama3.1:8b")),
+                ("coder".to_string(), model("codellama:13b")),
+            ]),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&raw).with_context(|| format!("Invalid agents file {}", path.display())),
+            _ => serde_yaml::from_str(&raw).with_context(|| format!("Invalid agents file {}", path.display())),
+        }
+    }
+
+    /// The entry for `agent_id` or its family, if any.
+    fn entry(&self, agent_id: &str) -> Option<&AgentSettings> {
+        let family = agent_id.split('-').next().unwrap_or(agent_id);
+        self.agents.get(agent_id).or_else(|| self.agents.get(family))
+    }
+
+    /// Whether `agent_id` has settings of its own, rather than only `default`.
+    pub fn is_configured(&self, agent_id: &str) -> bool {
+        self.entry(agent_id).is_some()
+    }
+
+    /// Settings for `agent_id`, field by field over the defaults.
+    pub fn resolve(&self, agent_id: &str) -> ResolvedAgent {
+        let agent = self.entry(agent_id);
+        let pick = |field: fn(&AgentSettings) -> Option<_>| agent.and_then(field).or_else(|| field(&self.default));
+
+        ResolvedAgent {
+            model: pick(|a| a.model.clone()).unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            temperature: agent.and_then(|a| a.temperature).or(self.default.temperature),
+            system_prompt: pick(|a| a.system_prompt.clone()),
+            context_tokens: agent
+                .and_then(|a| a.context_tokens)
+                .or(self.default.context_tokens)
+                .unwrap_or(DEFAULT_CONTEXT_TOKENS),
+            tools: agent
+                .and_then(|a| a.tools.clone())
+                .or_else(|| self.default.tools.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Tool names any agent is allowed, for checking against what is available.
+    pub fn allowed_tools(&self) -> Vec<&str> {
+        let mut tools: Vec<&str> = std::iter::once(&self.default)
+            .chain(self.agents.values())
+            .filter_map(|a| a.tools.as_ref())
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        tools.sort_unstable();
+        tools.dedup();
+        tools
+    }
+
+    /// Connect the configured backend.
+    pub fn backend(&self, client: Client) -> Result<Box<dyn LlmBackend>> {
+        let api_key = match &self.backend.api_key_env {
+            Some(var) => Some(std::env::var(var).with_context(|| format!("{} is not set", var))?),
+            None => None,
+        };
+        let url = self
+            .backend
+            .url
+            .clone()
+            .unwrap_or_else(|| self.backend.kind.default_url().to_string());
+        Ok(llm::backend(self.backend.kind, client, url, api_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_models() {
+        let config = AgentsConfig::builtin(BackendKind::OllamaGenerate, None);
+        assert_eq!(config.resolve("summarizer-1").model, "llama3.1:8b");
+        assert_eq!(config.resolve("coder-7").model, "codellama:13b");
+        assert_eq!(config.resolve("triage").model, "llama3.1:8b");
+        assert!(config.is_configured("coder-7"));
+        assert!(!config.is_configured("triage"));
+    }
+
+    #[test]
+    fn test_example_file_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("agents.example.yaml");
+        let config = AgentsConfig::load(&path).unwrap();
+        assert_eq!(config.resolve("coder-1").model, "codellama:13b");
+    }
+
+    #[test]
+    fn test_load_and_resolve() {
+        let path = std::env::temp_dir().join(format!("lornu-agents-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+backend:
+  kind: openai
+  url: http://vllm:8000/v1/chat/completions
+default:
+  model: qwen2.5:7b
+  temperature: 0.7
+  system_prompt: You are a Lornu AI agent.
+agents:
+  coder:
+    model: qwen2.5-coder:14b
+    temperature: 0.1
+    context_tokens: 32768
+    tools: [github_list_teams]
+  coder-review:
+    system_prompt: Review the diff.
+"#,
+        )
+        .unwrap();
+        let config = AgentsConfig::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.backend.kind, BackendKind::OpenAi);
+        assert_eq!(
+            config.resolve("coder-1"),
+            ResolvedAgent {
+                model: "qwen2.5-coder:14b".to_string(),
+                temperature: Some(0.1),
+                system_prompt: Some("You are a Lornu AI agent.".to_string()),
+                context_tokens: 32768,
+                tools: vec!["github_list_teams".to_string()],
+            }
+        );
+        // An exact agent_id entry wins over its family and fills gaps from the defaults
+        let review = config.resolve("coder-review");
+        assert_eq!(review.model, "qwen2.5:7b");
+        assert_eq!(review.system_prompt.as_deref(), Some("Review the diff."));
+        assert_eq!(config.resolve("summarizer-1").temperature, Some(0.7));
+        assert_eq!(config.resolve("summarizer-1").context_tokens, DEFAULT_CONTEXT_TOKENS);
+        assert!(config.resolve("summarizer-1").tools.is_empty());
+        assert_eq!(config.allowed_tools(), ["github_list_teams"]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Caller Identity
+//!
+//! The gateway authenticates callers and forwards who they are in
+//! [`IDENTITY_HEADER`], an HS256 token signed with `GATEWAY_IDENTITY_SECRET`.
+//! The worker verifies that token and keeps the caller with each task, so
+//! tools that change things can check the caller's scopes as well as the
+//! agent's tool list.
+//!
+//! Requests without the header run as an anonymous caller with no scopes;
+//! requests with a header that does not verify are rejected.
+
+use anyhow::{Context, Result};
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    Json,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{api_error, AppState};
+
+/// Header carrying the caller identity signed by the gateway.
+pub const IDENTITY_HEADER: &str = "x-lornu-identity";
+
+/// `iss` of identity tokens minted by the gateway.
+const IDENTITY_ISSUER: &str = "lornu-gateway";
+
+/// Shortest accepted `GATEWAY_IDENTITY_SECRET`, in bytes, as in the gateway.
+const MIN_IDENTITY_SECRET_LEN: usize = 32;
+
+/// Who submitted a task, as verified from the gateway's identity header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Caller {
+    pub subject: String,
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+impl Caller {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+}
+
+/// Claims of the gateway's identity token
+#[derive(Debug, Deserialize)]
+struct IdentityClaims {
+    sub: String,
+    preferred_username: String,
+    tenant: Option<String>,
+    /// Space-delimited scopes
+    scope: String,
+}
+
+/// Verifies the gateway's identity header
+#[derive(Default)]
+pub struct IdentityVerifier {
+    /// `None` when no secret is configured: every caller is anonymous
+    key: Option<DecodingKey>,
+}
+
+impl IdentityVerifier {
+    /// Configure from `GATEWAY_IDENTITY_SECRET`, the secret the gateway signs with.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("GATEWAY_IDENTITY_SECRET").ok().filter(|s| !s.is_empty()) {
+            Some(secret) => Self::new(secret.as_bytes()),
+            None => {
+                warn!("GATEWAY_IDENTITY_SECRET is not set; callers are anonymous and cannot use scoped tools");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn new(secret: &[u8]) -> Result<Self> {
+        if secret.len() < MIN_IDENTITY_SECRET_LEN {
+            anyhow::bail!("GATEWAY_IDENTITY_SECRET must be at least {} bytes", MIN_IDENTITY_SECRET_LEN);
+        }
+        Ok(Self {
+            key: Some(DecodingKey::from_secret(secret)),
+        })
+    }
+
+    /// Verify an identity token and return the caller it names.
+    pub fn verify(&self, token: &str) -> Result<Caller> {
+        let key = self.key.as_ref().context("Identity verification is not configured")?;
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[IDENTITY_ISSUER]);
+        validation.validate_aud = false;
+
+        let claims = jsonwebtoken::decode::<IdentityClaims>(token, key, &validation)
+            .context("Invalid identity token")?
+            .claims;
+        Ok(Caller {
+            subject: claims.sub,
+            user: claims.preferred_username,
+            tenant: claims.tenant,
+            scopes: claims.scope.split_whitespace().map(str::to_string).collect(),
+        })
+    }
+}
+
+/// Extractor: the verified caller, or `None` for requests that did not come
+/// through the gateway (or when no secret is configured).
+pub struct VerifiedCaller(pub Option<Caller>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for VerifiedCaller {
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IDENTITY_HEADER) else {
+            return Ok(Self(None));
+        };
+        if state.identity.key.is_none() {
+            return Ok(Self(None));
+        }
+
+        let token = value.to_str().unwrap_or_default();
+        match state.identity.verify(token) {
+            Ok(caller) => Ok(Self(Some(caller))),
+            Err(e) => {
+                warn!("Rejected {}: {:#}", parts.uri.path(), e);
+                Err(api_error(StatusCode::UNAUTHORIZED, "invalid_identity"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    pub(crate) const SECRET: &[u8] = b"test-identity-secret-0123456789abcdef";
+
+    pub(crate) fn token(secret: &[u8], issuer: &str, scope: &str) -> String {
+        let now = crate::store::now();
+        let claims = serde_json::json!({
+            "iss": issuer,
+            "sub": "user-123",
+            "preferred_username": "alice",
+            "tenant": "search",
+            "scope": scope,
+            "iat": now,
+            "exp": now + 60,
+        });
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    #[test]
+    fn test_verify_identity_token() {
+        let verifier = IdentityVerifier::new(SECRET).unwrap();
+        let caller = verifier.verify(&token(SECRET, IDENTITY_ISSUER, "dns:read dns:write")).unwrap();
+        assert_eq!(caller.subject, "user-123");
+        assert_eq!(caller.tenant.as_deref(), Some("search"));
+        assert!(caller.has_scope("dns:write"));
+
+        assert!(verifier.verify(&token(b"another-secret-0123456789abcdefghij", IDENTITY_ISSUER, "")).is_err());
+        assert!(verifier.verify(&token(SECRET, "someone-else", "")).is_err());
+        assert!(IdentityVerifier::default().verify(&token(SECRET, IDENTITY_ISSUER, "")).is_err());
+        assert!(IdentityVerifier::new(b"short").is_err());
+    }
+}
@@ -0,0 +1,888 @@
+//! LLM Backends
+//!
+//! The model servers tasks run against. Each backend translates an
+//! [`LlmRequest`] into its server's API:
+//!
+//! - `ollama_generate`: Ollama `/api/generate` (prompt + system; history is
+//!   written into the prompt as a transcript)
+//! - `ollama_chat`: Ollama `/api/chat` (messages)
+//! - `openai`: any OpenAI-compatible `/v1/chat/completions` server
+//!   (llama.cpp, vLLM, ...)
+//!
+//! The chat backends also support tool calling: the request lists the tools
+//! the model may call, and the response carries the calls it wants made.
+//!
+//! Backends can also stream: Ollama answers with NDJSON, one chunk per line,
+//! and OpenAI-compatible servers with server-sent events. Text is handed to
+//! a [`TokenSink`] as it arrives, and the full response is returned at the end.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// A completion request, after agent settings are applied
+#[derive(Debug, Clone)]
+pub struct LlmRequest {
+    pub model: String,
+    pub system: Option<String>,
+    pub prompt: String,
+    /// Earlier turns of the conversation, oldest first
+    pub history: Vec<Turn>,
+    /// Tools the model may call (chat backends only)
+    pub tools: Vec<ToolSpec>,
+    /// Tool calls the model made since `prompt` and their results
+    pub tool_rounds: Vec<Turn>,
+    pub temperature: Option<f32>,
+    /// Ollama generate conversation context; ignored by chat backends
+    pub context: serde_json::Value,
+}
+
+/// Who said a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+    /// The result of a tool call
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+/// A message from earlier in the conversation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub role: Role,
+    pub content: String,
+    /// Tools an assistant turn called
+    pub tool_calls: Vec<ToolCall>,
+    /// The call a tool turn is the result of
+    pub tool_call_id: Option<String>,
+}
+
+impl Turn {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments
+    pub parameters: serde_json::Value,
+}
+
+/// A tool call the model asked for
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolCall {
+    /// Id the result is sent back under
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// The model's answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmResponse {
+    pub text: String,
+    /// Model that answered, as reported by the server
+    pub model: String,
+    /// Tools to call before the model answers; `text` is then usually empty
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// Receives generated text as it arrives
+pub type TokenSink<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+/// A model server
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse>;
+
+    /// Like [`complete`](Self::complete), passing text to `on_token` as it is
+    /// generated. Backends without streaming deliver it in one piece.
+    async fn stream(&self, request: &LlmRequest, on_token: TokenSink<'_>) -> Result<LlmResponse> {
+        let resp = self.complete(request).await?;
+        on_token(&resp.text);
+        Ok(resp)
+    }
+}
+
+/// Kind of model server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    OllamaGenerate,
+    OllamaChat,
+    #[serde(rename = "openai")]
+    OpenAi,
+}
+
+impl BackendKind {
+    /// Endpoint used when none is configured.
+    pub fn default_url(&self) -> &'static str {
+        match self {
+            BackendKind::OllamaGenerate => "http://localhost:11434/api/generate",
+            BackendKind::OllamaChat => "http://localhost:11434/api/chat",
+            BackendKind::OpenAi => "http://localhost:8000/v1/chat/completions",
+        }
+    }
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ollama_generate" | "ollama" => Ok(BackendKind::OllamaGenerate),
+            "ollama_chat" => Ok(BackendKind::OllamaChat),
+            "openai" => Ok(BackendKind::OpenAi),
+            other => anyhow::bail!(
+                "Unknown LLM backend '{}' (expected ollama_generate, ollama_chat or openai)",
+                other
+            ),
+        }
+    }
+}
+
+/// Build the backend for `kind`.
+pub fn backend(kind: BackendKind, client: Client, url: String, api_key: Option<String>) -> Box<dyn LlmBackend> {
+    match kind {
+        BackendKind::OllamaGenerate => Box::new(OllamaGenerate { client, url }),
+        BackendKind::OllamaChat => Box::new(OllamaChat { client, url }),
+        BackendKind::OpenAi => Box::new(OpenAiChat { client, url, api_key }),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+impl<'a> ChatMessage<'a> {
+    fn new(role: &'static str, content: &'a str) -> Self {
+        Self {
+            role,
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+}
+
+/// System prompt, history, prompt and tool rounds as chat messages, with
+/// tool calls in the server's format.
+fn chat_messages(request: &LlmRequest, format_calls: fn(&[ToolCall]) -> serde_json::Value) -> Vec<ChatMessage<'_>> {
+    let system = request.system.as_deref().map(|content| ChatMessage::new("system", content));
+    let turn = |turn| turn_message(turn, format_calls);
+    system
+        .into_iter()
+        .chain(request.history.iter().map(turn))
+        .chain(std::iter::once(ChatMessage::new("user", &request.prompt)))
+        .chain(request.tool_rounds.iter().map(turn))
+        .collect()
+}
+
+fn turn_message(turn: &Turn, format_calls: fn(&[ToolCall]) -> serde_json::Value) -> ChatMessage<'_> {
+    ChatMessage {
+        role: turn.role.as_str(),
+        content: &turn.content,
+        tool_calls: (!turn.tool_calls.is_empty()).then(|| format_calls(&turn.tool_calls)),
+        tool_call_id: turn.tool_call_id.as_deref(),
+    }
+}
+
+/// `tools` in the OpenAI format, which Ollama shares.
+fn tool_definitions(tools: &[ToolSpec]) -> serde_json::Value {
+    tools
+        .iter()
+        .map(|tool| serde_json::json!({"type": "function", "function": tool}))
+        .collect()
+}
+
+/// Tool calls from a response. OpenAI sends the arguments as a JSON string,
+/// Ollama as an object and without ids. Arguments that do not parse are
+/// passed on as the raw string, for the tool to reject.
+fn tool_calls(raw: Option<Vec<RawToolCall>>) -> Vec<ToolCall> {
+    raw.unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(i, call)| {
+            let arguments = match call.function.arguments {
+                serde_json::Value::String(s) if s.trim().is_empty() => serde_json::json!({}),
+                serde_json::Value::String(s) => serde_json::from_str(&s).unwrap_or(serde_json::Value::String(s)),
+                serde_json::Value::Null => serde_json::json!({}),
+                other => other,
+            };
+            ToolCall {
+                id: call.id.unwrap_or_else(|| format!("call_{}", i)),
+                name: call.function.name,
+                arguments,
+            }
+        })
+        .collect()
+}
+
+/// Fail requests with tools on a backend that cannot call them.
+fn reject_tools(request: &LlmRequest, kind: &str) -> Result<()> {
+    if !request.tools.is_empty() {
+        anyhow::bail!("The {} backend does not support tool calling; use ollama_chat or openai", kind);
+    }
+    Ok(())
+}
+
+/// POST `body`, turning error statuses into errors.
+async fn post(builder: reqwest::RequestBuilder, body: &serde_json::Value) -> Result<reqwest::Response> {
+    let resp = builder.json(body).send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let detail = resp.text().await.unwrap_or_default();
+        anyhow::bail!("LLM server returned {}: {}", status, detail.chars().take(500).collect::<String>());
+    }
+    Ok(resp)
+}
+
+/// POST `body` and decode the JSON answer.
+async fn post_json<T: serde::de::DeserializeOwned>(
+    builder: reqwest::RequestBuilder,
+    body: &serde_json::Value,
+) -> Result<T> {
+    post(builder, body)
+        .await?
+        .json()
+        .await
+        .context("Invalid response from LLM server")
+}
+
+/// Call `f` with each non-empty line of a streamed response body as it arrives.
+async fn for_each_line(resp: reqwest::Response, mut f: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut body = resp.bytes_stream();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.next().await {
+        buf.extend_from_slice(&chunk?);
+        while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buf.drain(..=end).collect();
+            let line = std::str::from_utf8(&line).context("LLM stream is not UTF-8")?.trim();
+            if !line.is_empty() {
+                f(line)?;
+            }
+        }
+    }
+
+    let rest = std::str::from_utf8(&buf).context("LLM stream is not UTF-8")?.trim();
+    if !rest.is_empty() {
+        f(rest)?;
+    }
+    Ok(())
+}
+
+/// One line of an Ollama NDJSON stream (generate or chat)
+#[derive(Deserialize)]
+struct OllamaChunk {
+    #[serde(default)]
+    model: Option<String>,
+    /// Generate API
+    #[serde(default)]
+    response: Option<String>,
+    /// Chat API
+    #[serde(default)]
+    message: Option<ResponseMessage>,
+    #[serde(default)]
+    error: Option<String>,
+    /// Set on the last chunk
+    #[serde(default)]
+    done: bool,
+}
+
+/// Read an Ollama NDJSON stream to the end. A stream that closes before the
+/// chunk marked `done` is an error, not a short answer.
+async fn read_ollama_stream(resp: reqwest::Response, model: &str, on_token: TokenSink<'_>) -> Result<LlmResponse> {
+    let mut out = LlmResponse {
+        text: String::new(),
+        model: model.to_string(),
+        tool_calls: Vec::new(),
+    };
+    let mut done = false;
+    for_each_line(resp, |line| {
+        let chunk: OllamaChunk = serde_json::from_str(line).context("Invalid chunk in LLM stream")?;
+        if let Some(error) = chunk.error {
+            anyhow::bail!("LLM server failed mid-stream: {}", error);
+        }
+        done |= chunk.done;
+        if let Some(model) = chunk.model {
+            out.model = model;
+        }
+        let token = chunk.response.or_else(|| chunk.message.and_then(|m| m.content));
+        if let Some(token) = token.filter(|t| !t.is_empty()) {
+            on_token(&token);
+            out.text.push_str(&token);
+        }
+        Ok(())
+    })
+    .await?;
+    if !done {
+        anyhow::bail!("LLM stream ended before the response was complete");
+    }
+    Ok(out)
+}
+
+/// Ollama `/api/generate`
+pub struct OllamaGenerate {
+    client: Client,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    #[serde(default)]
+    model: Option<String>,
+    response: String,
+}
+
+impl OllamaGenerate {
+    /// The prompt, after a `User:`/`Assistant:` transcript of the history.
+    fn prompt(request: &LlmRequest) -> String {
+        if request.history.is_empty() {
+            return request.prompt.clone();
+        }
+        let mut prompt = String::new();
+        for turn in &request.history {
+            let speaker = match turn.role {
+                Role::User => "User",
+                Role::Assistant => "Assistant",
+                Role::Tool => "Tool",
+            };
+            prompt.push_str(&format!("{}: {}\n\n", speaker, turn.content));
+        }
+        prompt.push_str(&format!("User: {}\n\nAssistant:", request.prompt));
+        prompt
+    }
+
+    fn body(request: &LlmRequest, stream: bool) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "model": request.model,
+            "prompt": Self::prompt(request),
+            "stream": stream,
+            "context": request.context,
+        });
+        if let Some(system) = &request.system {
+            body["system"] = serde_json::json!(system);
+        }
+        if let Some(temperature) = request.temperature {
+            body["options"] = serde_json::json!({"temperature": temperature});
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OllamaGenerate {
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        reject_tools(request, "ollama_generate")?;
+        let body = Self::body(request, false);
+        let resp: GenerateResponse = post_json(self.client.post(&self.url), &body).await?;
+        Ok(LlmResponse {
+            text: resp.response,
+            model: resp.model.unwrap_or_else(|| request.model.clone()),
+            tool_calls: Vec::new(),
+        })
+    }
+
+    async fn stream(&self, request: &LlmRequest, on_token: TokenSink<'_>) -> Result<LlmResponse> {
+        reject_tools(request, "ollama_generate")?;
+        let resp = post(self.client.post(&self.url), &Self::body(request, true)).await?;
+        read_ollama_stream(resp, &request.model, on_token).await
+    }
+}
+
+/// Ollama `/api/chat`
+pub struct OllamaChat {
+    client: Client,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaChatResponse {
+    #[serde(default)]
+    model: Option<String>,
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<RawToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct RawToolCall {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: RawFunction,
+}
+
+/// Streamed deltas carry fragments, so every field is optional
+#[derive(Default, Deserialize)]
+struct RawFunction {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+/// Tool calls as Ollama takes them back: arguments as an object, no ids.
+fn ollama_tool_calls(calls: &[ToolCall]) -> serde_json::Value {
+    calls
+        .iter()
+        .map(|call| serde_json::json!({"function": {"name": call.name, "arguments": call.arguments}}))
+        .collect()
+}
+
+/// Tool calls as OpenAI takes them back: with ids, arguments as a JSON string.
+fn openai_tool_calls(calls: &[ToolCall]) -> serde_json::Value {
+    calls
+        .iter()
+        .map(|call| {
+            serde_json::json!({
+                "id": call.id,
+                "type": "function",
+                "function": {"name": call.name, "arguments": call.arguments.to_string()},
+            })
+        })
+        .collect()
+}
+
+impl OllamaChat {
+    fn body(request: &LlmRequest, stream: bool) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "model": request.model,
+            "messages": chat_messages(request, ollama_tool_calls),
+            "stream": stream,
+        });
+        if !request.tools.is_empty() {
+            body["tools"] = tool_definitions(&request.tools);
+        }
+        if let Some(temperature) = request.temperature {
+            body["options"] = serde_json::json!({"temperature": temperature});
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OllamaChat {
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let body = Self::body(request, false);
+        let resp: OllamaChatResponse = post_json(self.client.post(&self.url), &body).await?;
+        Ok(LlmResponse {
+            text: resp.message.content.unwrap_or_default(),
+            model: resp.model.unwrap_or_else(|| request.model.clone()),
+            tool_calls: tool_calls(resp.message.tool_calls),
+        })
+    }
+
+    async fn stream(&self, request: &LlmRequest, on_token: TokenSink<'_>) -> Result<LlmResponse> {
+        let resp = post(self.client.post(&self.url), &Self::body(request, true)).await?;
+        read_ollama_stream(resp, &request.model, on_token).await
+    }
+}
+
+/// OpenAI-compatible `/v1/chat/completions`
+pub struct OpenAiChat {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiResponse {
+    #[serde(default)]
+    model: Option<String>,
+    choices: Vec<OpenAiChoice>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    message: ResponseMessage,
+}
+
+/// One `data:` event of an OpenAI stream
+#[derive(Deserialize)]
+struct OpenAiChunk {
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<OpenAiDelta>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiDelta {
+    delta: ResponseMessage,
+}
+
+impl OpenAiChat {
+    fn request(&self, request: &LlmRequest, stream: bool) -> (reqwest::RequestBuilder, serde_json::Value) {
+        let mut body = serde_json::json!({
+            "model": request.model,
+            "messages": chat_messages(request, openai_tool_calls),
+            "stream": stream,
+        });
+        if !request.tools.is_empty() {
+            body["tools"] = tool_definitions(&request.tools);
+        }
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = serde_json::json!(temperature);
+        }
+
+        let mut builder = self.client.post(&self.url);
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        (builder, body)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiChat {
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse> {
+        let (builder, body) = self.request(request, false);
+        let resp: OpenAiResponse = post_json(builder, &body).await?;
+        let choice = resp.choices.into_iter().next().context("LLM server returned no choices")?;
+        Ok(LlmResponse {
+            text: choice.message.content.unwrap_or_default(),
+            model: resp.model.unwrap_or_else(|| request.model.clone()),
+            tool_calls: tool_calls(choice.message.tool_calls),
+        })
+    }
+
+    async fn stream(&self, request: &LlmRequest, on_token: TokenSink<'_>) -> Result<LlmResponse> {
+        let (builder, body) = self.request(request, true);
+        let resp = post(builder, &body).await?;
+
+        let mut out = LlmResponse {
+            text: String::new(),
+            model: request.model.clone(),
+            tool_calls: Vec::new(),
+        };
+        let mut done = false;
+        for_each_line(resp, |line| {
+            // Comments, event names and the closing `[DONE]` carry no text
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(());
+            };
+            if data == "[DONE]" {
+                done = true;
+                return Ok(());
+            }
+            let chunk: OpenAiChunk = serde_json::from_str(data).context("Invalid chunk in LLM stream")?;
+            if let Some(model) = chunk.model {
+                out.model = model;
+            }
+            let token = chunk.choices.into_iter().next().and_then(|c| c.delta.content);
+            if let Some(token) = token.filter(|t| !t.is_empty()) {
+                on_token(&token);
+                out.text.push_str(&token);
+            }
+            Ok(())
+        })
+        .await?;
+        // Without `[DONE]` the connection dropped mid-answer
+        if !done {
+            anyhow::bail!("LLM stream ended before the response was complete");
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    type Seen = Arc<Mutex<Vec<(Option<String>, serde_json::Value)>>>;
+
+    /// Server answering every request with `reply`, recording what it got.
+    async fn spawn_server(reply: serde_json::Value) -> (String, Seen) {
+        let seen: Seen = Arc::default();
+        let app = Router::new()
+            .route(
+                "/",
+                post(
+                    |State((seen, reply)): State<(Seen, serde_json::Value)>,
+                     headers: HeaderMap,
+                     Json(body): Json<serde_json::Value>| async move {
+                        let auth = headers
+                            .get("authorization")
+                            .map(|v| v.to_str().unwrap().to_string());
+                        seen.lock().unwrap().push((auth, body));
+                        Json(reply)
+                    },
+                ),
+            )
+            .with_state((seen.clone(), reply));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/", addr), seen)
+    }
+
+    /// Server answering every request with the raw `body` lines.
+    async fn spawn_stream_server(lines: &'static [&'static str]) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move || async move {
+                let chunks = lines.iter().map(|line| Ok::<_, std::io::Error>(format!("{}\n", line)));
+                axum::body::Body::from_stream(futures::stream::iter(chunks))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/", addr)
+    }
+
+    /// Stream `request()` through `llm`, returning the tokens seen and the response.
+    async fn collect(llm: &dyn LlmBackend) -> Result<(Vec<String>, LlmResponse)> {
+        let tokens = Mutex::new(Vec::new());
+        let resp = llm
+            .stream(&request(), &|t: &str| tokens.lock().unwrap().push(t.to_string()))
+            .await?;
+        Ok((tokens.into_inner().unwrap(), resp))
+    }
+
+    fn request() -> LlmRequest {
+        LlmRequest {
+            model: "llama3.1:8b".to_string(),
+            system: Some("Be brief.".to_string()),
+            prompt: "Summarize the release notes".to_string(),
+            history: Vec::new(),
+            tools: Vec::new(),
+            tool_rounds: Vec::new(),
+            temperature: Some(0.2),
+            context: serde_json::Value::Null,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ollama_generate() {
+        let (url, seen) = spawn_server(serde_json::json!({"model": "llama3.1:8b", "response": "Done."})).await;
+        let llm = backend(BackendKind::OllamaGenerate, Client::new(), url, None);
+
+        let resp = llm.complete(&request()).await.unwrap();
+        assert_eq!(resp.text, "Done.");
+
+        let (_, body) = seen.lock().unwrap().remove(0);
+        assert_eq!(body["prompt"], "Summarize the release notes");
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["options"]["temperature"], 0.2f32 as f64);
+        assert_eq!(body["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_chat() {
+        let reply = serde_json::json!({"model": "llama3.1:8b", "message": {"role": "assistant", "content": "Done."}});
+        let (url, seen) = spawn_server(reply).await;
+        let llm = backend(BackendKind::OllamaChat, Client::new(), url, None);
+
+        assert_eq!(llm.complete(&request()).await.unwrap().text, "Done.");
+        let (_, body) = seen.lock().unwrap().remove(0);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "Summarize the release notes");
+    }
+
+    #[test]
+    fn test_history_placement() {
+        let mut req = request();
+        req.history = vec![
+            Turn::new(Role::User, "Hi"),
+            Turn::new(Role::Assistant, "Hello!"),
+        ];
+
+        let roles: Vec<_> = chat_messages(&req, openai_tool_calls).iter().map(|m| m.role).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert_eq!(
+            OllamaGenerate::body(&req, false)["prompt"],
+            "User: Hi\n\nAssistant: Hello!\n\nUser: Summarize the release notes\n\nAssistant:"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openai_chat() {
+        let reply = serde_json::json!({
+            "model": "qwen2.5-coder",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Done."}}],
+        });
+        let (url, seen) = spawn_server(reply).await;
+        let llm = backend(BackendKind::OpenAi, Client::new(), url, Some("sk-local".to_string()));
+
+        let resp = llm.complete(&request()).await.unwrap();
+        let expected = LlmResponse {
+            text: "Done.".to_string(),
+            model: "qwen2.5-coder".to_string(),
+            tool_calls: Vec::new(),
+        };
+        assert_eq!(resp, expected);
+
+        let (auth, body) = seen.lock().unwrap().remove(0);
+        assert_eq!(auth.as_deref(), Some("Bearer sk-local"));
+        assert_eq!(body["temperature"], 0.2f32 as f64);
+        assert_eq!(body["messages"].as_array().unwrap().len(), 2);
+    }
+
+    /// `request()` with a tool and one round of calling it behind it.
+    fn tool_request() -> LlmRequest {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "list_teams".to_string(),
+            arguments: serde_json::json!({}),
+        };
+        let mut req = request();
+        req.tools = vec![ToolSpec {
+            name: "list_teams".to_string(),
+            description: "List the organization's teams".to_string(),
+            parameters: serde_json::json!({"type": "object", "properties": {}}),
+        }];
+        req.tool_rounds = vec![
+            Turn {
+                tool_calls: vec![call],
+                ..Turn::new(Role::Assistant, "")
+            },
+            Turn {
+                tool_call_id: Some("call_1".to_string()),
+                ..Turn::new(Role::Tool, r#"["platform"]"#)
+            },
+        ];
+        req
+    }
+
+    #[tokio::test]
+    async fn test_openai_tool_calls() {
+        let call = serde_json::json!({
+            "id": "call_2",
+            "type": "function",
+            "function": {"name": "add_member", "arguments": "{\"team\": \"platform\"}"},
+        });
+        let reply = serde_json::json!({
+            "choices": [{"message": {"role": "assistant", "content": null, "tool_calls": [call]}}],
+        });
+        let (url, seen) = spawn_server(reply).await;
+        let llm = backend(BackendKind::OpenAi, Client::new(), url, None);
+
+        let resp = llm.complete(&tool_request()).await.unwrap();
+        assert_eq!(resp.text, "");
+        assert_eq!(resp.tool_calls[0].id, "call_2");
+        assert_eq!(resp.tool_calls[0].arguments, serde_json::json!({"team": "platform"}));
+
+        let (_, body) = seen.lock().unwrap().remove(0);
+        assert_eq!(body["tools"][0]["function"]["name"], "list_teams");
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["tool_calls"][0]["function"]["arguments"], "{}");
+        assert_eq!(messages[3]["role"], "tool");
+        assert_eq!(messages[3]["tool_call_id"], "call_1");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_chat_tool_calls() {
+        let call = serde_json::json!({"function": {"name": "add_member", "arguments": {"team": "platform"}}});
+        let reply = serde_json::json!({"message": {"role": "assistant", "content": "", "tool_calls": [call]}});
+        let (url, seen) = spawn_server(reply).await;
+        let llm = backend(BackendKind::OllamaChat, Client::new(), url, None);
+
+        let resp = llm.complete(&tool_request()).await.unwrap();
+        assert_eq!(resp.tool_calls[0].id, "call_0");
+        assert_eq!(resp.tool_calls[0].arguments, serde_json::json!({"team": "platform"}));
+
+        let (_, body) = seen.lock().unwrap().remove(0);
+        assert_eq!(body["messages"][2]["tool_calls"][0]["function"]["arguments"], serde_json::json!({}));
+
+        let generate = backend(BackendKind::OllamaGenerate, Client::new(), "http://unused".to_string(), None);
+        assert!(generate.complete(&tool_request()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_stream() {
+        let url = spawn_stream_server(&[
+            r#"{"model":"llama3.1:8b","response":"Hel","done":false}"#,
+            r#"{"model":"llama3.1:8b","response":"lo.","done":false}"#,
+            r#"{"model":"llama3.1:8b","response":"","done":true,"eval_count":2}"#,
+        ])
+        .await;
+        let llm = backend(BackendKind::OllamaGenerate, Client::new(), url, None);
+
+        let (tokens, resp) = collect(llm.as_ref()).await.unwrap();
+        assert_eq!(tokens, ["Hel", "lo."]);
+        assert_eq!(resp.text, "Hello.");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_stream_error() {
+        let url = spawn_stream_server(&[
+            r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            r#"{"error":"model unloaded"}"#,
+        ])
+        .await;
+        let llm = backend(BackendKind::OllamaChat, Client::new(), url, None);
+
+        let err = collect(llm.as_ref()).await.unwrap_err();
+        assert!(err.to_string().contains("model unloaded"));
+
+        // Closed before the chunk marked done
+        let url = spawn_stream_server(&[r#"{"model":"llama3.1:8b","response":"Hel","done":false}"#]).await;
+        let llm = backend(BackendKind::OllamaGenerate, Client::new(), url, None);
+        let err = collect(llm.as_ref()).await.unwrap_err();
+        assert!(err.to_string().contains("before the response was complete"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream() {
+        let url = spawn_stream_server(&[
+            r#"data: {"model":"qwen2.5-coder","choices":[{"index":0,"delta":{"role":"assistant"}}]}"#,
+            "",
+            r#"data: {"model":"qwen2.5-coder","choices":[{"index":0,"delta":{"content":"Do"}}]}"#,
+            ": keep-alive",
+            r#"data: {"model":"qwen2.5-coder","choices":[{"index":0,"delta":{"content":"ne."}}]}"#,
+            "data: [DONE]",
+        ])
+        .await;
+        let llm = backend(BackendKind::OpenAi, Client::new(), url, None);
+
+        let (tokens, resp) = collect(llm.as_ref()).await.unwrap();
+        assert_eq!(tokens, ["Do", "ne."]);
+        assert_eq!(resp.model, "qwen2.5-coder");
+
+        let url = spawn_stream_server(&[
+            r#"data: {"model":"qwen2.5-coder","choices":[{"index":0,"delta":{"content":"Do"}}]}"#,
+        ])
+        .await;
+        let llm = backend(BackendKind::OpenAi, Client::new(), url, None);
+        assert!(collect(llm.as_ref()).await.is_err());
+    }
+}
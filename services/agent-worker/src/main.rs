@@ -3,30 +3,82 @@
 //! Executes agent tasks by processing messages from a task queue.
 
 use anyhow::Result;
-use async_channel::{bounded, Receiver, Sender};
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
     routing::{get, post},
     Json, Router,
 };
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::future::IntoFuture;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch, Notify, Semaphore};
+use tokio::task::JoinSet;
 use tower_http::cors::CorsLayer;
-use tracing::{info, Level};
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 use uuid::Uuid;
 
+mod agents;
+mod auth;
+mod llm;
+mod metrics;
+mod pool;
+mod sessions;
+mod store;
+mod stream;
+mod tools;
+
+use agents::{AgentsConfig, ResolvedAgent};
+use auth::{Caller, IdentityVerifier, VerifiedCaller};
+use llm::{LlmBackend, LlmRequest, Role};
+use pool::AgentSlots;
+use sessions::{Message, SessionStore, TurnOutcome};
+use store::{Cancelled, Cursor, Enqueued, QueuedTask, TaskFilter, TaskState, TaskStore};
+use tools::ToolRegistry;
+
+/// Seconds to keep working through the queue, and serving open connections,
+/// after a shutdown signal.
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 25;
+
+/// How often finished tasks past the retention window are purged.
+const PURGE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Fallback poll interval for the processor when no wakeup arrives.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 struct AppState {
-    task_tx: Sender<(String, TaskRequest)>,
-    task_rx: Receiver<(String, TaskRequest)>,
-    active_tasks: Arc<RwLock<Vec<TaskStatus>>>,
-    http_client: Client,
-    llm_endpoint: String,
+    store: Arc<dyn TaskStore>,
+    /// Signals the processor that a task was queued
+    wakeup: Arc<Notify>,
+    /// Set on shutdown: the processor exits once the queue is empty
+    draining: Arc<AtomicBool>,
+    /// Aborts for running tasks, by task id
+    running: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+    /// Running tasks per agent, against the pool limits
+    slots: Arc<AgentSlots>,
+    /// Timeout for tasks that do not set their own
+    task_timeout: Duration,
+    /// Model server tasks run against
+    llm: Arc<dyn LlmBackend>,
+    /// Per-agent model settings
+    agents: Arc<AgentsConfig>,
+    /// Output of running tasks, for streaming clients
+    streams: Arc<stream::TaskStreams>,
+    /// Conversations, for tasks that continue one
+    sessions: Arc<dyn SessionStore>,
+    /// Tools agents may be allowed to call
+    tools: Arc<ToolRegistry>,
+    /// Checks the caller identity the gateway forwards
+    identity: Arc<IdentityVerifier>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,14 +87,20 @@ pub struct TaskRequest {
     pub prompt: String,
     #[serde(default)]
     pub context: serde_json::Value,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TaskStatus {
-    pub task_id: String,
-    pub agent_id: String,
-    pub status: String,
-    pub result: Option<serde_json::Value>,
+    /// Higher runs first (default: 0)
+    #[serde(default)]
+    pub priority: i32,
+    /// Overrides the worker's task timeout, up to one hour
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Session the task is a turn of; its history is sent along and the
+    /// exchange appended on success
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Who submitted the task, from the gateway's identity header; set by
+    /// the worker, never taken from the request body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caller: Option<Caller>,
 }
 
 #[tokio::main]
@@ -54,71 +112,281 @@ async fn main() -> Result<()> {
 
     info!("Starting Lornu AI Agent Worker");
 
-    let (task_tx, task_rx) = bounded(1000);
-    let llm_endpoint = env::var("LLM_ENDPOINT")
-        .unwrap_or_else(|_| "http://localhost:11434/api/generate".to_string());
+    let prometheus = metrics::install()?;
 
+    let agents = AgentsConfig::from_env()?;
+    // No overall timeout: tasks are bounded by `pool::task_timeout`
+    let llm = agents.backend(Client::builder().connect_timeout(Duration::from_secs(10)).build()?)?;
+    info!("Using {:?} LLM backend with {} configured agents", agents.backend.kind, agents.agents.len());
+
+    let tools = ToolRegistry::from_env();
+    info!("Tools available to agents: {:?}", tools.names());
+    for name in agents.allowed_tools() {
+        if !tools.names().contains(&name) {
+            warn!("Agents are allowed tool {}, which is not available", name);
+        }
+    }
+
+    let limits = pool::Limits::from_env();
+    let task_timeout = env::var("WORKER_TASK_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(pool::DEFAULT_TASK_TIMEOUT);
+    info!(
+        "Running up to {} tasks at once ({} per agent by default), timeout {}s",
+        limits.global,
+        limits.per_agent_default,
+        task_timeout.as_secs()
+    );
+
+    let (store, sessions) = store::from_env()?;
     let state = AppState {
-        task_tx,
-        task_rx: task_rx.clone(),
-        active_tasks: Arc::new(RwLock::new(Vec::new())),
-        http_client: Client::builder()
-            .timeout(std::time::Duration::from_secs(120))
-            .build()?,
-        llm_endpoint,
+        store,
+        wakeup: Arc::new(Notify::new()),
+        draining: Arc::new(AtomicBool::new(false)),
+        running: Arc::default(),
+        slots: AgentSlots::new(limits),
+        task_timeout,
+        llm: llm.into(),
+        agents: Arc::new(agents),
+        streams: Arc::default(),
+        sessions,
+        tools: Arc::new(tools),
+        identity: Arc::new(IdentityVerifier::from_env()?),
     };
 
-    // Start background processor
-    let processor_state = state.clone();
-    tokio::spawn(async move {
-        loop {
-            if let Ok((task_id, req)) = processor_state.task_rx.recv().await {
-                info!("Processing task: {}", task_id);
-
-                // Update to running
-                {
-                    let mut tasks = processor_state.active_tasks.write().await;
-                    if let Some(t) = tasks.iter_mut().find(|t| t.task_id == task_id) {
-                        t.status = "running".to_string();
-                    }
-                }
+    // Tasks still marked running were interrupted by the previous shutdown or a crash
+    let requeued = state.store.requeue_running().await?;
+    if requeued > 0 {
+        info!("Re-queued {} interrupted tasks", requeued);
+    }
+    metrics::record_queue_depth(state.store.queued().await?);
 
-                // Execute
-                let result = execute_task(&processor_state, &req).await;
-
-                // Update result
-                {
-                    let mut tasks = processor_state.active_tasks.write().await;
-                    if let Some(t) = tasks.iter_mut().find(|t| t.task_id == task_id) {
-                        match result {
-                            Ok(output) => {
-                                t.status = "completed".to_string();
-                                t.result = Some(output);
-                            }
-                            Err(e) => {
-                                t.status = "failed".to_string();
-                                t.result = Some(serde_json::json!({"error": e.to_string()}));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    });
+    let retention = env::var("WORKER_TASK_RETENTION_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(store::DEFAULT_RETENTION_SECS);
+    tokio::spawn(purge_finished(state.store.clone(), retention));
+
+    let processor = tokio::spawn(run_processor(state.clone()));
 
-    let app = Router::new()
+    let app = api_routes()
         .route("/health", get(health_check))
-        .route("/api/tasks", post(submit_task))
-        .route("/api/tasks", get(list_tasks))
+        .route("/metrics", get(move || std::future::ready(prometheus.render())))
+        .layer(middleware::from_fn(metrics::track_requests))
         .layer(CorsLayer::permissive())
-        .with_state(state);
+        .with_state(state.clone());
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 8082));
     info!("Agent Worker listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let (signal_tx, signal_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = signal_tx.send(true);
+    });
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(signalled(signal_rx.clone()))
+        .into_future();
 
+    // The queue drains and open connections (task streams) finish under the
+    // same grace period, both starting at the signal
+    let grace = env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(DEFAULT_SHUTDOWN_GRACE_SECS));
+    let serve = async {
+        tokio::select! {
+            result = server => result?,
+            _ = async {
+                signalled(signal_rx.clone()).await;
+                tokio::time::sleep(grace).await;
+            } => warn!("Grace period expired; closing open connections"),
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+    let drain = async {
+        signalled(signal_rx.clone()).await;
+        drain_queue(&state, processor, grace).await
+    };
+    tokio::try_join!(serve, drain)?;
+    Ok(())
+}
+
+/// Dispatch queued tasks to the worker pool, highest priority first, while
+/// the global and per-agent limits allow.
+///
+/// Running tasks belong to the pool's `JoinSet`, so aborting the processor
+/// aborts them too.
+async fn run_processor(state: AppState) {
+    let permits = Arc::new(Semaphore::new(state.slots.limits().global));
+    let mut workers = JoinSet::new();
+
+    loop {
+        while workers.try_join_next().is_some() {}
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+
+        match state.store.claim_next(&state.slots.saturated()).await {
+            Ok(Some(task)) => {
+                // Before any await, so a cancel from here on reaches the task
+                let (abort_tx, abort_rx) = oneshot::channel();
+                state.running().insert(task.task_id.clone(), abort_tx);
+                let slot = state.slots.acquire(&task.request.agent_id);
+                metrics::record_running(state.slots.total());
+                let state = state.clone();
+                workers.spawn(async move {
+                    process_task(&state, task, abort_rx).await;
+                    drop((slot, permit));
+                    metrics::record_running(state.slots.total());
+                    // An agent may have dropped below its limit
+                    state.wakeup.notify_one();
+                });
+            }
+            Ok(None) => {
+                drop(permit);
+                if state.draining.load(Ordering::SeqCst) && workers.is_empty() {
+                    return;
+                }
+                tokio::select! {
+                    Some(_) = workers.join_next() => {}
+                    _ = tokio::time::timeout(POLL_INTERVAL, state.wakeup.notified()) => {}
+                }
+            }
+            Err(e) => {
+                drop(permit);
+                error!("Failed to read the task queue: {:#}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Run a claimed task, whose abort handle is already registered.
+async fn process_task(state: &AppState, task: QueuedTask, abort_rx: oneshot::Receiver<()>) {
+    let QueuedTask { task_id, request: req } = task;
+    // Cancelled after being claimed but before its abort handle existed
+    if let Ok(true) = state.cancelled(&task_id).await {
+        state.running().remove(&task_id);
+        info!("Task {} cancelled before it started", task_id);
+        return;
+    }
+    info!("Processing task: {}", task_id);
+    if let Ok(depth) = state.store.queued().await {
+        metrics::record_queue_depth(depth);
+    }
+
+    // Dropped last, after the result is stored, so streaming clients that
+    // see the output end find the task finished
+    let live = state.streams.register(&task_id);
+    let agent = state.agents.resolve(&req.agent_id);
+    let timeout = pool::task_timeout(req.timeout_secs, state.task_timeout);
+    let started = Instant::now();
+    // Execute until done or cancelled; dropping the future aborts the LLM request
+    let result = tokio::select! {
+        result = tokio::time::timeout(timeout, execute_task(state, &task_id, &agent, &req, &live)) => {
+            result.unwrap_or_else(|_| Err(anyhow::anyhow!("Task timed out after {}s", timeout.as_secs())))
+        }
+        _ = abort_rx => {
+            info!("Task {} cancelled while running", task_id);
+            return;
+        }
+    };
+    state.running().remove(&task_id);
+    metrics::record_task(&agent.model, result.is_ok(), started.elapsed());
+
+    // Update result
+    let (outcome, result) = match result {
+        Ok(output) => (TaskState::Completed, output),
+        Err(e) => (TaskState::Failed, serde_json::json!({"error": e.to_string()})),
+    };
+    if let Err(e) = state.store.finish(&task_id, outcome, result).await {
+        error!("Failed to record the result of task {}: {:#}", task_id, e);
+    }
+}
+
+impl AppState {
+    fn running(&self) -> std::sync::MutexGuard<'_, HashMap<String, oneshot::Sender<()>>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether `task_id` has been cancelled.
+    async fn cancelled(&self, task_id: &str) -> Result<bool> {
+        let task = self.store.get(task_id).await?;
+        Ok(task.is_some_and(|t| t.status == TaskState::Cancelled))
+    }
+}
+
+/// Periodically delete finished tasks older than `retention_secs`.
+async fn purge_finished(store: Arc<dyn TaskStore>, retention_secs: u64) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match store.purge_finished(store::now().saturating_sub(retention_secs)).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} finished tasks past retention", purged),
+            Err(e) => warn!("Failed to purge finished tasks: {:#}", e),
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl+C.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, no longer accepting tasks");
+}
+
+/// Resolves once the shutdown signal has been received.
+async fn signalled(mut signal: watch::Receiver<bool>) {
+    let _ = signal.wait_for(|received| *received).await;
+}
+
+/// Let the processor work through the queue for up to `grace`. Whatever is
+/// left stays in the task store; an interrupted task is re-queued so the next
+/// start picks it up first.
+async fn drain_queue(state: &AppState, mut processor: tokio::task::JoinHandle<()>, grace: Duration) -> Result<()> {
+    state.draining.store(true, Ordering::SeqCst);
+    state.wakeup.notify_one();
+    info!("Draining {} queued tasks (grace period: {}s)", state.store.queued().await?, grace.as_secs());
+
+    if tokio::time::timeout(grace, &mut processor).await.is_ok() {
+        info!("Task queue drained");
+        return Ok(());
+    }
+
+    processor.abort();
+    let _ = processor.await;
+
+    let interrupted = state.store.requeue_running().await?;
+    warn!(
+        "Grace period expired; leaving {} unfinished tasks ({} interrupted) for the next start",
+        state.store.queued().await?,
+        interrupted
+    );
     Ok(())
 }
 
@@ -130,56 +398,578 @@ async fn health_check() -> Json<serde_json::Value> {
     }))
 }
 
+type ApiResult = Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)>;
+
+/// The task API (submit, list, look up and cancel tasks) and the session API.
+fn api_routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/tasks", get(list_tasks).post(submit_task))
+        .route("/api/tasks/:id", get(get_task).delete(cancel_task))
+        .route("/api/tasks/:id/stream", get(stream::stream_task))
+        .route("/api/sessions", post(sessions::create_session))
+        .route("/api/sessions/:id", get(sessions::get_session).delete(sessions::delete_session))
+        .route("/api/sessions/:id/messages", post(sessions::post_message))
+}
+
 async fn submit_task(
     State(state): State<AppState>,
-    Json(req): Json<TaskRequest>,
-) -> Json<serde_json::Value> {
+    VerifiedCaller(caller): VerifiedCaller,
+    Json(mut req): Json<TaskRequest>,
+) -> ApiResult {
+    req.caller = caller;
+    let task_id = enqueue_task(&state, &req).await?;
+    Ok(Json(serde_json::json!({"task_id": task_id, "status": TaskState::Queued})))
+}
+
+/// Queue `req` and wake the processor, returning the new task's id.
+async fn enqueue_task(state: &AppState, req: &TaskRequest) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    // Connections still open during shutdown may not add to the queue being drained
+    if state.draining.load(Ordering::SeqCst) {
+        return Err(api_error(StatusCode::SERVICE_UNAVAILABLE, "shutting_down"));
+    }
     let task_id = Uuid::new_v4().to_string();
+    if let Enqueued::SessionBusy(turn) = state.store.enqueue(&task_id, req).await.map_err(store_error)? {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": "session_busy", "task_id": turn})),
+        ));
+    }
+    state.wakeup.notify_one();
+    if let Ok(depth) = state.store.queued().await {
+        metrics::record_queue_depth(depth);
+    }
+    Ok(task_id)
+}
 
-    let status = TaskStatus {
-        task_id: task_id.clone(),
-        agent_id: req.agent_id.clone(),
-        status: "queued".to_string(),
-        result: None,
+/// Query parameters of `GET /api/tasks`
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    agent_id: Option<String>,
+    status: Option<TaskState>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn list_tasks(State(state): State<AppState>, Query(query): Query<ListQuery>) -> ApiResult {
+    let cursor = match query.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(c) => Some(
+            c.parse::<Cursor>()
+                .map_err(|_| api_error(StatusCode::BAD_REQUEST, "invalid_cursor"))?,
+        ),
+        None => None,
     };
+    let filter = TaskFilter {
+        agent_id: query.agent_id,
+        status: query.status,
+        cursor,
+        limit: query.limit.unwrap_or(store::DEFAULT_PAGE_SIZE).clamp(1, store::MAX_PAGE_SIZE),
+    };
+
+    let page = state.store.list(&filter).await.map_err(store_error)?;
+    Ok(Json(serde_json::json!({
+        "tasks": page.tasks,
+        "count": page.tasks.len(),
+        "next_cursor": page.next_cursor.map(|c| c.to_string()),
+    })))
+}
+
+async fn get_task(State(state): State<AppState>, Path(task_id): Path<String>) -> ApiResult {
+    match state.store.get(&task_id).await.map_err(store_error)? {
+        Some(task) => Ok(Json(serde_json::json!(task))),
+        None => Err(api_error(StatusCode::NOT_FOUND, "task_not_found")),
+    }
+}
+
+/// Cancel a queued task, or abort a running one.
+async fn cancel_task(State(state): State<AppState>, Path(task_id): Path<String>) -> ApiResult {
+    match state.store.cancel(&task_id).await.map_err(store_error)? {
+        None => return Err(api_error(StatusCode::NOT_FOUND, "task_not_found")),
+        Some(Cancelled::AlreadyFinished(status)) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error": "task_finished", "status": status})),
+            ))
+        }
+        Some(Cancelled::Running) => {
+            if let Some(abort) = state.running().remove(&task_id) {
+                let _ = abort.send(());
+            }
+        }
+        Some(Cancelled::Queued) => {}
+    }
 
-    state.active_tasks.write().await.push(status);
-    let _ = state.task_tx.send((task_id.clone(), req)).await;
+    info!("Cancelled task {}", task_id);
+    Ok(Json(serde_json::json!({"task_id": task_id, "status": TaskState::Cancelled})))
+}
 
-    Json(serde_json::json!({"task_id": task_id, "status": "queued"}))
+fn api_error(status: StatusCode, error: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (status, Json(serde_json::json!({"error": error})))
 }
 
-async fn list_tasks(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let tasks = state.active_tasks.read().await;
-    Json(serde_json::json!({"tasks": tasks.clone(), "count": tasks.len()}))
+fn store_error(e: anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    error!("Task store error: {:#}", e);
+    api_error(StatusCode::SERVICE_UNAVAILABLE, "task_store_unavailable")
 }
 
-async fn execute_task(state: &AppState, req: &TaskRequest) -> Result<serde_json::Value> {
-    let model = match req.agent_id.split('-').next().unwrap_or("") {
-        "summarizer" => "llama3.1:8b",
-        "coder" => "codellama:13b",
-        _ => "llama3.1:8b",
+async fn execute_task(
+    state: &AppState,
+    task_id: &str,
+    agent: &ResolvedAgent,
+    req: &TaskRequest,
+    live: &stream::LiveOutput,
+) -> Result<serde_json::Value> {
+    let session = match &req.session_id {
+        Some(id) => Some(
+            state
+                .sessions
+                .get(id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Session {} not found", id))?,
+        ),
+        None => None,
     };
-
-    let llm_req = serde_json::json!({
-        "model": model,
-        "prompt": req.prompt,
-        "stream": false,
-        "context": req.context
+    let system = session
+        .as_ref()
+        .and_then(|s| s.system_prompt.clone())
+        .or_else(|| agent.system_prompt.clone());
+    let history = session.as_ref().map_or_else(Vec::new, |s| {
+        sessions::history_for_turn(s, system.as_deref(), &req.prompt, agent.context_tokens)
     });
 
-    let resp = state
-        .http_client
-        .post(&state.llm_endpoint)
-        .json(&llm_req)
-        .send()
-        .await?;
+    let llm_req = LlmRequest {
+        model: agent.model.clone(),
+        system,
+        prompt: req.prompt.clone(),
+        history,
+        tools: Vec::new(),
+        tool_rounds: Vec::new(),
+        temperature: agent.temperature,
+        context: req.context.clone(),
+    };
 
-    let result: serde_json::Value = resp.json().await?;
+    // Tool rounds run unstreamed; the answer is published when it arrives
+    let mut tool_calls = Vec::new();
+    let resp = if !agent.tools.is_empty() {
+        let caller = req.caller.as_ref();
+        let (resp, trace) = tools::run(state.llm.as_ref(), &state.tools, &agent.tools, caller, llm_req).await?;
+        live.push(&resp.text);
+        tool_calls = trace;
+        resp
+    } else if state.agents.backend.stream {
+        state.llm.stream(&llm_req, &|token: &str| live.push(token)).await?
+    } else {
+        state.llm.complete(&llm_req).await?
+    };
 
-    Ok(serde_json::json!({
+    let mut output = serde_json::json!({
         "agent_id": req.agent_id,
-        "response": result.get("response"),
-        "model": model
-    }))
+        "response": resp.text,
+        "model": resp.model
+    });
+    if !tool_calls.is_empty() {
+        output["tool_calls"] = serde_json::json!(tool_calls);
+    }
+
+    if let Some(session_id) = &req.session_id {
+        output["session_id"] = serde_json::json!(session_id);
+        // The turn joins the conversation only together with completing the
+        // task, so a cancelled turn never does
+        let at = store::now();
+        let exchange = [
+            Message { role: Role::User, content: req.prompt.clone(), created_at: at },
+            Message { role: Role::Assistant, content: resp.text.clone(), created_at: at },
+        ];
+        match state.sessions.complete_turn(task_id, session_id, &exchange, &output).await? {
+            TurnOutcome::Completed => {}
+            TurnOutcome::TaskNotRunning => anyhow::bail!("Task {} was cancelled", task_id),
+            TurnOutcome::SessionNotFound => anyhow::bail!("Session {} was deleted", session_id),
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use futures::StreamExt;
+    use tower::ServiceExt;
+
+    /// LLM stand-in answering "done", streamed as "do" + "ne" when asked to.
+    /// Prompts containing "hang" never get an answer; with "slow" the two
+    /// chunks come 200ms apart.
+    async fn spawn_llm() -> String {
+        let app = Router::new().route(
+            "/api/generate",
+            post(|Json(body): Json<serde_json::Value>| async move {
+                let prompt = body["prompt"].as_str().unwrap_or("").to_string();
+                if prompt.contains("hang") {
+                    std::future::pending::<()>().await;
+                }
+                if body["stream"] != true {
+                    return Body::from(serde_json::json!({"response": "done"}).to_string());
+                }
+
+                let delay = Duration::from_millis(if prompt.contains("slow") { 200 } else { 0 });
+                let chunks = ["do", "ne"].into_iter().map(|t| serde_json::json!({"response": t, "done": false}));
+                let lines = chunks.chain(std::iter::once(serde_json::json!({"response": "", "done": true})));
+                let body = futures::stream::iter(lines).then(move |line| async move {
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, std::io::Error>(format!("{}\n", line))
+                });
+                Body::from_stream(body)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/api/generate", addr)
+    }
+
+    async fn test_state() -> AppState {
+        let tasks = Arc::new(store::InMemoryTaskStore::default());
+        AppState {
+            store: tasks.clone(),
+            wakeup: Arc::new(Notify::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            running: Arc::default(),
+            slots: AgentSlots::new(pool::Limits {
+                global: 2,
+                per_agent_default: 1,
+                per_agent: HashMap::new(),
+            }),
+            task_timeout: Duration::from_secs(5),
+            llm: llm::backend(llm::BackendKind::OllamaGenerate, Client::new(), spawn_llm().await, None).into(),
+            agents: Arc::new(AgentsConfig::builtin(llm::BackendKind::OllamaGenerate, None)),
+            streams: Arc::default(),
+            sessions: Arc::new(sessions::InMemorySessionStore::new(tasks)),
+            tools: Arc::default(),
+            identity: Arc::default(),
+        }
+    }
+
+    async fn call(
+        state: &AppState,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        call_as(state, None, method, uri, body).await
+    }
+
+    /// `call` with the gateway's identity header set to `identity`.
+    async fn call_as(
+        state: &AppState,
+        identity: Option<&str>,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(identity) = identity {
+            req = req.header(auth::IDENTITY_HEADER, identity);
+        }
+        let body = match body {
+            Some(json) => {
+                req = req.header("content-type", "application/json");
+                Body::from(json.to_string())
+            }
+            None => Body::empty(),
+        };
+        let resp = api_routes()
+            .with_state(state.clone())
+            .oneshot(req.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+    }
+
+    async fn submit(state: &AppState, agent_id: &str, prompt: &str) -> String {
+        submit_with(state, serde_json::json!({"agent_id": agent_id, "prompt": prompt})).await
+    }
+
+    async fn submit_with(state: &AppState, body: serde_json::Value) -> String {
+        let (_, task) = call(state, "POST", "/api/tasks", Some(body)).await;
+        task["task_id"].as_str().unwrap().to_string()
+    }
+
+    async fn wait_for(state: &AppState, task_id: &str, status: &str) {
+        for _ in 0..200 {
+            let (_, task) = call(state, "GET", &format!("/api/tasks/{}", task_id), None).await;
+            if task["status"] == status {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("task {} never reached {}", task_id, status);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_task_frees_the_processor() {
+        let state = test_state().await;
+        tokio::spawn(run_processor(state.clone()));
+
+        // Same agent, limited to one task at a time
+        let hung = submit(&state, "summarizer-1", "hang").await;
+        let next = submit(&state, "summarizer-1", "hi").await;
+        wait_for(&state, &hung, "running").await;
+
+        let (status, body) = call(&state, "DELETE", &format!("/api/tasks/{}", hung), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "cancelled");
+
+        wait_for(&state, &next, "completed").await;
+        let (_, task) = call(&state, "GET", &format!("/api/tasks/{}", hung), None).await;
+        assert_eq!(task["status"], "cancelled");
+
+        let (status, body) = call(&state, "DELETE", &format!("/api/tasks/{}", next), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["status"], "completed");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_between_claim_and_start() {
+        let state = test_state().await;
+        let body = serde_json::json!({"agent_id": "summarizer-1"});
+        let (_, session) = call(&state, "POST", "/api/sessions", Some(body)).await;
+        let uri = format!("/api/sessions/{}", session["session_id"].as_str().unwrap());
+        let turn = serde_json::json!({"content": "hi"});
+        let (_, turn) = call(&state, "POST", &format!("{}/messages", uri), Some(turn)).await;
+        let task_uri = format!("/api/tasks/{}", turn["task_id"].as_str().unwrap());
+
+        // As the processor does: claim, then register the abort handle
+        let task = state.store.claim_next(&[]).await.unwrap().unwrap();
+        let (abort_tx, abort_rx) = oneshot::channel();
+        state.running().insert(task.task_id.clone(), abort_tx);
+
+        let (status, _) = call(&state, "DELETE", &task_uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        process_task(&state, task, abort_rx).await;
+
+        let (_, task) = call(&state, "GET", &task_uri, None).await;
+        assert_eq!(task["status"], "cancelled");
+        let (_, stored) = call(&state, "GET", &uri, None).await;
+        assert_eq!(stored["messages"], serde_json::json!([]));
+        assert!(state.running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_busy_agent_does_not_block_others() {
+        let state = test_state().await;
+        tokio::spawn(run_processor(state.clone()));
+
+        // summarizer-1 is capped at one task, so its second task waits while
+        // the coder task, submitted last, runs on the other slot
+        let hung = submit(&state, "summarizer-1", "hang").await;
+        let waiting = submit(&state, "summarizer-1", "hi").await;
+        let coder = submit(&state, "coder-1", "hi").await;
+
+        wait_for(&state, &coder, "completed").await;
+        wait_for(&state, &hung, "running").await;
+        let (_, task) = call(&state, "GET", &format!("/api/tasks/{}", waiting), None).await;
+        assert_eq!(task["status"], "queued");
+
+        call(&state, "DELETE", &format!("/api/tasks/{}", hung), None).await;
+        wait_for(&state, &waiting, "completed").await;
+    }
+
+    #[tokio::test]
+    async fn test_caller_comes_from_the_identity_header() {
+        let mut state = test_state().await;
+        state.identity = Arc::new(IdentityVerifier::new(auth::tests::SECRET).unwrap());
+        let submit = |identity: Option<String>| {
+            let body = serde_json::json!({
+                "agent_id": "summarizer-1",
+                "prompt": "hi",
+                "caller": {"subject": "admin", "user": "admin", "scopes": ["dns:write"]},
+            });
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/api/tasks")
+                .header("content-type", "application/json");
+            if let Some(identity) = identity {
+                req = req.header(auth::IDENTITY_HEADER, identity);
+            }
+            api_routes().with_state(state.clone()).oneshot(req.body(Body::from(body.to_string())).unwrap())
+        };
+
+        // A caller in the body is ignored
+        assert_eq!(submit(None).await.unwrap().status(), StatusCode::OK);
+        assert_eq!(state.store.claim_next(&[]).await.unwrap().unwrap().request.caller, None);
+
+        let identity = auth::tests::token(auth::tests::SECRET, "lornu-gateway", "dns:read");
+        assert_eq!(submit(Some(identity)).await.unwrap().status(), StatusCode::OK);
+        let caller = state.store.claim_next(&[]).await.unwrap().unwrap().request.caller.unwrap();
+        assert_eq!((caller.subject.as_str(), caller.scopes), ("user-123", vec!["dns:read".to_string()]));
+
+        let forged = auth::tests::token(b"not-the-gateway-secret-0123456789abc", "lornu-gateway", "dns:write");
+        assert_eq!(submit(Some(forged)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(state.store.queued().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_refuses_new_tasks() {
+        let state = test_state().await;
+        let processor = tokio::spawn(run_processor(state.clone()));
+        let queued = submit(&state, "summarizer-1", "hi").await;
+
+        drain_queue(&state, processor, Duration::from_secs(5)).await.unwrap();
+        let (_, task) = call(&state, "GET", &format!("/api/tasks/{}", queued), None).await;
+        assert_eq!(task["status"], "completed");
+
+        let body = serde_json::json!({"agent_id": "summarizer-1", "prompt": "hi"});
+        let (status, body) = call(&state, "POST", "/api/tasks", Some(body)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["error"], "shutting_down");
+    }
+
+    #[tokio::test]
+    async fn test_task_timeout_fails_the_task() {
+        let state = test_state().await;
+        tokio::spawn(run_processor(state.clone()));
+
+        let body = serde_json::json!({"agent_id": "summarizer-1", "prompt": "hang", "timeout_secs": 1});
+        let task_id = submit_with(&state, body).await;
+        wait_for(&state, &task_id, "failed").await;
+
+        let (_, task) = call(&state, "GET", &format!("/api/tasks/{}", task_id), None).await;
+        assert_eq!(task["result"]["error"], "Task timed out after 1s");
+    }
+
+    #[tokio::test]
+    async fn test_stream_task_output() {
+        let state = test_state().await;
+        let task_id = submit(&state, "summarizer-1", "slow").await;
+
+        let resp = api_routes()
+            .with_state(state.clone())
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/tasks/{}/stream", task_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()["content-type"], "text/event-stream");
+
+        // Start the task only once the client is watching
+        tokio::spawn(run_processor(state.clone()));
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let events: Vec<(String, serde_json::Value)> = String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .split("\n\n")
+            .filter_map(|event| {
+                let name = event.lines().find_map(|l| l.strip_prefix("event: "))?;
+                let data = event.lines().find_map(|l| l.strip_prefix("data: "))?;
+                Some((name.to_string(), serde_json::from_str(data).unwrap()))
+            })
+            .collect();
+
+        assert_eq!(events[0], ("status".to_string(), serde_json::json!({"status": "queued"})));
+        let text: String = events
+            .iter()
+            .filter(|(name, _)| name == "token")
+            .map(|(_, data)| data["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(text, "done");
+        let (name, task) = events.last().unwrap();
+        assert_eq!(name, "done");
+        assert_eq!(task["status"], "completed");
+        assert_eq!(task["result"]["response"], "done");
+    }
+
+    #[tokio::test]
+    async fn test_list_filters_and_pages() {
+        let state = test_state().await;
+        for agent in ["summarizer-1", "coder-1", "summarizer-1", "summarizer-1"] {
+            submit(&state, agent, "hi").await;
+        }
+
+        let (_, page) = call(&state, "GET", "/api/tasks?agent_id=summarizer-1&status=queued&limit=2", None).await;
+        assert_eq!(page["count"], 2);
+        let cursor = page["next_cursor"].as_str().unwrap().to_string();
+
+        let uri = format!("/api/tasks?agent_id=summarizer-1&limit=2&cursor={}", cursor);
+        let (_, page) = call(&state, "GET", &uri, None).await;
+        assert_eq!(page["count"], 1);
+        assert!(page["next_cursor"].is_null());
+
+        let (status, _) = call(&state, "GET", "/api/tasks?cursor=zz", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&state, "GET", "/api/tasks/missing", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_session_keeps_completed_turns() {
+        let state = test_state().await;
+        tokio::spawn(run_processor(state.clone()));
+
+        let body = serde_json::json!({"agent_id": "summarizer-1"});
+        let (_, session) = call(&state, "POST", "/api/sessions", Some(body)).await;
+        let messages = format!("/api/sessions/{}/messages", session["session_id"].as_str().unwrap());
+
+        let (_, turn) = call(&state, "POST", &messages, Some(serde_json::json!({"content": "hi"}))).await;
+        let task_id = turn["task_id"].as_str().unwrap();
+        wait_for(&state, task_id, "completed").await;
+        let (_, task) = call(&state, "GET", &format!("/api/tasks/{}", task_id), None).await;
+        assert_eq!(task["result"]["session_id"], session["session_id"]);
+
+        // A cancelled turn leaves the history as it was
+        let (_, turn) = call(&state, "POST", &messages, Some(serde_json::json!({"content": "hang"}))).await;
+        let task_id = turn["task_id"].as_str().unwrap();
+        wait_for(&state, task_id, "running").await;
+        // One turn at a time
+        let (status, busy) = call(&state, "POST", &messages, Some(serde_json::json!({"content": "next"}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(busy["task_id"], task_id);
+        call(&state, "DELETE", &format!("/api/tasks/{}", task_id), None).await;
+
+        let uri = format!("/api/sessions/{}", session["session_id"].as_str().unwrap());
+        let (_, stored) = call(&state, "GET", &uri, None).await;
+        let history: Vec<_> = stored["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| (m["role"].as_str().unwrap(), m["content"].as_str().unwrap()))
+            .collect();
+        assert_eq!(history, [("user", "hi"), ("assistant", "done")]);
+
+        let (status, _) = call(&state, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&state, "POST", &messages, Some(serde_json::json!({"content": "hi"}))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_belong_to_their_creator() {
+        let mut state = test_state().await;
+        state.identity = Arc::new(IdentityVerifier::new(auth::tests::SECRET).unwrap());
+        let alice = auth::tests::token(auth::tests::SECRET, "lornu-gateway", "");
+
+        let body = serde_json::json!({"agent_id": "summarizer-1"});
+        let (_, session) = call_as(&state, Some(&alice), "POST", "/api/sessions", Some(body)).await;
+        assert_eq!(session["owner"], "user-123");
+        let uri = format!("/api/sessions/{}", session["session_id"].as_str().unwrap());
+        let (status, _) = call_as(&state, Some(&alice), "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Anyone else, anonymous callers included, finds no such session
+        let message = Some(serde_json::json!({"content": "hi"}));
+        assert_eq!(call(&state, "GET", &uri, None).await.0, StatusCode::NOT_FOUND);
+        let (status, _) = call(&state, "POST", &format!("{}/messages", uri), message).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(call(&state, "DELETE", &uri, None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(state.store.queued().await.unwrap(), 0);
+        let (status, _) = call_as(&state, Some(&alice), "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let body = serde_json::json!({"agent_id": "triage-1"});
+        let (status, error) = call_as(&state, Some(&alice), "POST", "/api/sessions", Some(body)).await;
+        assert_eq!((status, error["error"].as_str()), (StatusCode::BAD_REQUEST, Some("unknown_agent")));
+    }
 }
@@ -0,0 +1,64 @@
+//! Metrics
+//!
+//! Prometheus metrics for the agent worker, exposed at `/metrics`: requests
+//! per matched route, task queue depth, running tasks, and task outcomes and durations.
+
+use anyhow::Result;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::time::Duration;
+
+pub use lornu_http_metrics::track_requests;
+
+/// Buckets (seconds) for LLM tasks, which run for seconds to minutes.
+const TASK_BUCKETS: [f64; 10] = [0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Time spent executing a task, by model and outcome.
+const TASK_DURATION: &str = "worker_task_duration_seconds";
+
+/// Install the global Prometheus recorder.
+pub fn install() -> Result<PrometheusHandle> {
+    lornu_http_metrics::install(&[(TASK_DURATION, &TASK_BUCKETS)])
+}
+
+/// Record the number of tasks waiting in the queue.
+pub fn record_queue_depth(depth: usize) {
+    metrics::gauge!("worker_queue_depth").set(depth as f64);
+}
+
+/// Record the number of tasks executing.
+pub fn record_running(running: usize) {
+    metrics::gauge!("worker_tasks_running").set(running as f64);
+}
+
+/// Record a finished task.
+pub fn record_task(model: &str, succeeded: bool, elapsed: Duration) {
+    let outcome = if succeeded { "completed" } else { "failed" };
+    metrics::counter!("worker_tasks_total", "model" => model.to_string(), "outcome" => outcome).increment(1);
+    metrics::histogram!(TASK_DURATION, "model" => model.to_string(), "outcome" => outcome)
+        .record(elapsed.as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    fn handle() -> &'static PrometheusHandle {
+        static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+        HANDLE.get_or_init(|| install().unwrap())
+    }
+
+    #[test]
+    fn test_task_metrics() {
+        let handle = handle();
+        record_queue_depth(3);
+        record_task("llama3.1:8b", true, Duration::from_secs(4));
+
+        let rendered = handle.render();
+        assert!(rendered.contains("worker_queue_depth 3"));
+        assert!(rendered.contains(r#"worker_tasks_total{model="llama3.1:8b",outcome="completed"} 1"#));
+        assert!(rendered.contains(
+            r#"worker_task_duration_seconds_bucket{model="llama3.1:8b",outcome="completed",le="5"} 1"#
+        ));
+    }
+}
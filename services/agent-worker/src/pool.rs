@@ -0,0 +1,178 @@
+//! Worker Pool Limits
+//!
+//! How many tasks run at once: a global cap for the worker, and per-agent
+//! caps so one busy agent (say a backlog of summaries) cannot take every slot
+//! and starve the others.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Tasks run concurrently when `WORKER_CONCURRENCY` is unset.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Task timeout when `WORKER_TASK_TIMEOUT_SECS` is unset.
+pub const DEFAULT_TASK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Upper bound for a timeout requested by a task.
+pub const MAX_TASK_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Concurrency caps
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Tasks running at once across all agents
+    pub global: usize,
+    /// Cap for agents without their own entry
+    pub per_agent_default: usize,
+    /// Caps by agent id
+    pub per_agent: HashMap<String, usize>,
+}
+
+impl Limits {
+    /// Limits from the environment.
+    ///
+    /// - `WORKER_CONCURRENCY`: global cap (default: 4)
+    /// - `WORKER_AGENT_CONCURRENCY`: cap per agent (default: the global cap)
+    /// - `WORKER_AGENT_LIMITS`: per-agent overrides, e.g. `summarizer-1=3,coder-1=1`
+    pub fn from_env() -> Self {
+        let number = |var: &str| {
+            std::env::var(var)
+                .ok()
+                .and_then(|s| s.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+        };
+        let global = number("WORKER_CONCURRENCY").unwrap_or(DEFAULT_CONCURRENCY);
+
+        Self {
+            global,
+            per_agent_default: number("WORKER_AGENT_CONCURRENCY").unwrap_or(global),
+            per_agent: parse_agent_limits(&std::env::var("WORKER_AGENT_LIMITS").unwrap_or_default()),
+        }
+    }
+
+    pub fn for_agent(&self, agent_id: &str) -> usize {
+        self.per_agent.get(agent_id).copied().unwrap_or(self.per_agent_default)
+    }
+}
+
+/// Parse `agent=limit` pairs, skipping malformed entries.
+fn parse_agent_limits(raw: &str) -> HashMap<String, usize> {
+    raw.split(',')
+        .filter_map(|pair| {
+            let (agent, limit) = pair.split_once('=')?;
+            let limit = limit.trim().parse().ok().filter(|n| *n > 0)?;
+            Some((agent.trim().to_string(), limit))
+        })
+        .collect()
+}
+
+/// Tasks currently running, by agent
+pub struct AgentSlots {
+    limits: Limits,
+    running: Mutex<HashMap<String, usize>>,
+}
+
+impl AgentSlots {
+    pub fn new(limits: Limits) -> Arc<Self> {
+        Arc::new(Self {
+            limits,
+            running: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    fn running(&self) -> std::sync::MutexGuard<'_, HashMap<String, usize>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Agents at their cap, whose queued tasks must wait.
+    pub fn saturated(&self) -> Vec<String> {
+        self.running()
+            .iter()
+            .filter(|(agent, count)| **count >= self.limits.for_agent(agent))
+            .map(|(agent, _)| agent.clone())
+            .collect()
+    }
+
+    /// Total tasks running.
+    pub fn total(&self) -> usize {
+        self.running().values().sum()
+    }
+
+    /// Take a slot for `agent_id`, released when the returned guard drops.
+    pub fn acquire(self: &Arc<Self>, agent_id: &str) -> AgentSlot {
+        *self.running().entry(agent_id.to_string()).or_default() += 1;
+        AgentSlot {
+            slots: self.clone(),
+            agent_id: agent_id.to_string(),
+        }
+    }
+}
+
+/// A running task's claim on its agent's capacity
+pub struct AgentSlot {
+    slots: Arc<AgentSlots>,
+    agent_id: String,
+}
+
+impl Drop for AgentSlot {
+    fn drop(&mut self) {
+        let mut running = self.slots.running();
+        if let Some(count) = running.get_mut(&self.agent_id) {
+            *count -= 1;
+            if *count == 0 {
+                running.remove(&self.agent_id);
+            }
+        }
+    }
+}
+
+/// Timeout for a task: its own request, capped at [`MAX_TASK_TIMEOUT`], or the default.
+pub fn task_timeout(requested_secs: Option<u64>, default: Duration) -> Duration {
+    match requested_secs {
+        Some(secs) if secs > 0 => Duration::from_secs(secs).min(MAX_TASK_TIMEOUT),
+        _ => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_slots_saturate_per_agent() {
+        let slots = AgentSlots::new(Limits {
+            global: 4,
+            per_agent_default: 2,
+            per_agent: parse_agent_limits("coder-1=1, bad, summarizer-1=x"),
+        });
+
+        let coder = slots.acquire("coder-1");
+        let first = slots.acquire("summarizer-1");
+        assert_eq!(slots.saturated(), vec!["coder-1".to_string()]);
+
+        let second = slots.acquire("summarizer-1");
+        let mut saturated = slots.saturated();
+        saturated.sort();
+        assert_eq!(saturated, ["coder-1", "summarizer-1"]);
+        assert_eq!(slots.total(), 3);
+
+        drop(coder);
+        assert_eq!(slots.saturated(), vec!["summarizer-1".to_string()]);
+        drop((first, second));
+        assert!(slots.saturated().is_empty());
+        assert_eq!(slots.total(), 0);
+    }
+
+    #[test]
+    fn test_task_timeout() {
+        let default = Duration::from_secs(120);
+        assert_eq!(task_timeout(None, default), default);
+        assert_eq!(task_timeout(Some(0), default), default);
+        assert_eq!(task_timeout(Some(600), default), Duration::from_secs(600));
+        assert_eq!(task_timeout(Some(u64::MAX), default), MAX_TASK_TIMEOUT);
+    }
+}
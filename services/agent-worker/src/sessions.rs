@@ -0,0 +1,605 @@
+//! Conversation Sessions
+//!
+//! A session is a conversation with one agent. Each message posted to it runs
+//! as a task with the session's history, truncated from the oldest end to fit
+//! the agent's context window. The message and the reply are appended to the
+//! session in the same step that completes the task; a failed or cancelled
+//! turn leaves the history unchanged, so it can simply be retried.
+//!
+//! A session belongs to the caller who created it (see [`crate::auth`]); to
+//! anyone else it does not exist.
+//!
+//! - `POST /api/sessions`: `{"agent_id", "system_prompt"?}`
+//! - `GET /api/sessions/:id`, `DELETE /api/sessions/:id`
+//! - `POST /api/sessions/:id/messages`: `{"content", "priority"?, "timeout_secs"?}`
+//!   queues a turn and returns its task, which can be polled or streamed like
+//!   any other
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::StatusCode,
+    Json,
+};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::auth::{Caller, VerifiedCaller};
+use crate::llm::{Role, Turn};
+use crate::store::{now, InMemoryTaskStore, TaskState};
+use crate::{api_error, enqueue_task, store_error, ApiResult, AppState, TaskRequest};
+
+/// Estimated characters per token, for English text and code.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimated tokens of framing per message (role markers, separators).
+const TOKENS_PER_MESSAGE: usize = 4;
+
+/// A message in a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// Unix seconds
+    pub created_at: u64,
+}
+
+/// A conversation with one agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub session_id: String,
+    pub agent_id: String,
+    /// Subject of the caller who created it; `None` for anonymous callers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Overrides the agent's system prompt
+    pub system_prompt: Option<String>,
+    pub messages: Vec<Message>,
+    /// Unix seconds
+    pub created_at: u64,
+}
+
+/// Rough token count of `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) + TOKENS_PER_MESSAGE
+}
+
+/// The most recent messages whose estimated size fits in `budget` tokens.
+pub fn truncate_history(messages: &[Message], budget: usize) -> &[Message] {
+    let mut used = 0;
+    let mut start = messages.len();
+    for (i, message) in messages.iter().enumerate().rev() {
+        used += estimate_tokens(&message.content);
+        if used > budget {
+            break;
+        }
+        start = i;
+    }
+    &messages[start..]
+}
+
+/// The history a turn runs with: as much as fits in `context_tokens` after
+/// the system prompt, the new message and a quarter of the window kept free
+/// for the reply.
+pub fn history_for_turn(
+    session: &Session,
+    system_prompt: Option<&str>,
+    prompt: &str,
+    context_tokens: usize,
+) -> Vec<Turn> {
+    let fixed = system_prompt.map_or(0, estimate_tokens) + estimate_tokens(prompt);
+    let budget = (context_tokens - context_tokens / 4).saturating_sub(fixed);
+    truncate_history(&session.messages, budget)
+        .iter()
+        .map(|m| Turn::new(m.role, m.content.clone()))
+        .collect()
+}
+
+/// Persistence for sessions
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn create(&self, session: &Session) -> Result<()>;
+
+    async fn get(&self, session_id: &str) -> Result<Option<Session>>;
+
+    /// Append a turn's messages and complete its task with `result`, as one
+    /// step: a task cancelled before it (or a deleted session) changes neither.
+    async fn complete_turn(
+        &self,
+        task_id: &str,
+        session_id: &str,
+        messages: &[Message],
+        result: &serde_json::Value,
+    ) -> Result<TurnOutcome>;
+
+    /// Delete a session and its messages; `false` if it did not exist.
+    async fn delete(&self, session_id: &str) -> Result<bool>;
+}
+
+/// Outcome of [`SessionStore::complete_turn`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnOutcome {
+    Completed,
+    /// The task was cancelled (or otherwise finished) first
+    TaskNotRunning,
+    SessionNotFound,
+}
+
+/// Session store that lives and dies with the process, next to its tasks
+pub struct InMemorySessionStore {
+    tasks: Arc<InMemoryTaskStore>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl InMemorySessionStore {
+    pub fn new(tasks: Arc<InMemoryTaskStore>) -> Self {
+        Self {
+            tasks,
+            sessions: Mutex::default(),
+        }
+    }
+
+    fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn create(&self, session: &Session) -> Result<()> {
+        self.sessions().insert(session.session_id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Session>> {
+        Ok(self.sessions().get(session_id).cloned())
+    }
+
+    async fn complete_turn(
+        &self,
+        task_id: &str,
+        session_id: &str,
+        messages: &[Message],
+        result: &serde_json::Value,
+    ) -> Result<TurnOutcome> {
+        let Some(task) = self.tasks.lock_running(task_id) else {
+            return Ok(TurnOutcome::TaskNotRunning);
+        };
+        let mut sessions = self.sessions();
+        let Some(session) = sessions.get_mut(session_id) else {
+            return Ok(TurnOutcome::SessionNotFound);
+        };
+        session.messages.extend_from_slice(messages);
+        task.complete(result.clone());
+        Ok(TurnOutcome::Completed)
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<bool> {
+        Ok(self.sessions().remove(session_id).is_some())
+    }
+}
+
+/// Session store in the worker's SQLite task database
+pub struct SqliteSessionStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteSessionStore {
+    /// Open (or create) the session tables in the task database at `path`,
+    /// which must already hold the task table.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn =
+            Connection::open(path).with_context(|| format!("Failed to open session database {}", path.display()))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             CREATE TABLE IF NOT EXISTS sessions (
+                 session_id    TEXT PRIMARY KEY,
+                 agent_id      TEXT NOT NULL,
+                 owner         TEXT,
+                 system_prompt TEXT,
+                 created_at    INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS session_messages (
+                 seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+                 session_id TEXT NOT NULL,
+                 role       TEXT NOT NULL,
+                 content    TEXT NOT NULL,
+                 created_at INTEGER NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS session_messages_session ON session_messages (session_id, seq);",
+        )
+        .context("Failed to initialise session tables")?;
+
+        // Databases created before sessions had owners
+        let has_owner = conn
+            .prepare("SELECT 1 FROM pragma_table_info('sessions') WHERE name = 'owner'")?
+            .exists([])?;
+        if !has_owner {
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN owner TEXT;")
+                .context("Failed to migrate session tables")?;
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run a query on the blocking pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+fn parse_role(role: &str) -> Result<Role> {
+    match role {
+        "user" => Ok(Role::User),
+        "assistant" => Ok(Role::Assistant),
+        other => anyhow::bail!("Unknown message role '{}'", other),
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStore {
+    async fn create(&self, session: &Session) -> Result<()> {
+        let session = session.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO sessions (session_id, agent_id, owner, system_prompt, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    session.session_id,
+                    session.agent_id,
+                    session.owner,
+                    session.system_prompt,
+                    session.created_at as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Option<Session>> {
+        let session_id = session_id.to_string();
+        self.with_conn(move |conn| {
+            let session = conn
+                .query_row(
+                    "SELECT agent_id, owner, system_prompt, created_at FROM sessions WHERE session_id = ?1",
+                    params![session_id],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, Option<String>>(2)?,
+                            row.get::<_, i64>(3)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            let Some((agent_id, owner, system_prompt, created_at)) = session else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(
+                "SELECT role, content, created_at FROM session_messages WHERE session_id = ?1 ORDER BY seq",
+            )?;
+            let rows = stmt.query_map(params![session_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?;
+            let mut messages = Vec::new();
+            for row in rows {
+                let (role, content, created_at) = row?;
+                messages.push(Message {
+                    role: parse_role(&role)?,
+                    content,
+                    created_at: created_at as u64,
+                });
+            }
+
+            Ok(Some(Session {
+                session_id,
+                agent_id,
+                owner,
+                system_prompt,
+                messages,
+                created_at: created_at as u64,
+            }))
+        })
+        .await
+    }
+
+    async fn complete_turn(
+        &self,
+        task_id: &str,
+        session_id: &str,
+        messages: &[Message],
+        result: &serde_json::Value,
+    ) -> Result<TurnOutcome> {
+        let task_id = task_id.to_string();
+        let session_id = session_id.to_string();
+        let messages = messages.to_vec();
+        let result = serde_json::to_string(result)?;
+        self.with_conn(move |conn| {
+            // Takes the write lock up front, so the task cannot be cancelled
+            // between the check and the commit
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let completed = tx.execute(
+                "UPDATE tasks SET status = ?1, result = ?2, finished_at = ?3 WHERE task_id = ?4 AND status = ?5",
+                params![
+                    TaskState::Completed.as_str(),
+                    result,
+                    now() as i64,
+                    task_id,
+                    TaskState::Running.as_str()
+                ],
+            )?;
+            if completed == 0 {
+                return Ok(TurnOutcome::TaskNotRunning);
+            }
+            let exists = tx
+                .query_row("SELECT 1 FROM sessions WHERE session_id = ?1", params![session_id], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if !exists {
+                return Ok(TurnOutcome::SessionNotFound);
+            }
+            for message in &messages {
+                tx.execute(
+                    "INSERT INTO session_messages (session_id, role, content, created_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![session_id, message.role.as_str(), message.content, message.created_at as i64],
+                )?;
+            }
+            tx.commit()?;
+            Ok(TurnOutcome::Completed)
+        })
+        .await
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<bool> {
+        let session_id = session_id.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM session_messages WHERE session_id = ?1", params![session_id])?;
+            let deleted = tx.execute("DELETE FROM sessions WHERE session_id = ?1", params![session_id])?;
+            tx.commit()?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+}
+
+/// Body of `POST /api/sessions`
+#[derive(Debug, Deserialize)]
+pub struct CreateSession {
+    pub agent_id: String,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+/// Body of `POST /api/sessions/:id/messages`
+#[derive(Debug, Deserialize)]
+pub struct PostMessage {
+    pub content: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// The session `session_id` if `caller` created it. Other callers' sessions
+/// are not found, so their ids give nothing away.
+async fn owned_session(
+    state: &AppState,
+    session_id: &str,
+    caller: Option<&Caller>,
+) -> Result<Session, (StatusCode, Json<serde_json::Value>)> {
+    state
+        .sessions
+        .get(session_id)
+        .await
+        .map_err(store_error)?
+        .filter(|s| s.owner.as_deref() == caller.map(|c| c.subject.as_str()))
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "session_not_found"))
+}
+
+pub async fn create_session(
+    State(state): State<AppState>,
+    VerifiedCaller(caller): VerifiedCaller,
+    Json(req): Json<CreateSession>,
+) -> ApiResult {
+    // Caught here rather than by every turn
+    if !state.agents.is_configured(&req.agent_id) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "unknown_agent"));
+    }
+    let session = Session {
+        session_id: Uuid::new_v4().to_string(),
+        agent_id: req.agent_id,
+        owner: caller.map(|c| c.subject),
+        system_prompt: req.system_prompt,
+        messages: Vec::new(),
+        created_at: now(),
+    };
+    state.sessions.create(&session).await.map_err(store_error)?;
+    Ok(Json(serde_json::json!(session)))
+}
+
+pub async fn get_session(
+    State(state): State<AppState>,
+    VerifiedCaller(caller): VerifiedCaller,
+    UrlPath(session_id): UrlPath<String>,
+) -> ApiResult {
+    let session = owned_session(&state, &session_id, caller.as_ref()).await?;
+    Ok(Json(serde_json::json!(session)))
+}
+
+pub async fn delete_session(
+    State(state): State<AppState>,
+    VerifiedCaller(caller): VerifiedCaller,
+    UrlPath(session_id): UrlPath<String>,
+) -> ApiResult {
+    owned_session(&state, &session_id, caller.as_ref()).await?;
+    if !state.sessions.delete(&session_id).await.map_err(store_error)? {
+        return Err(api_error(StatusCode::NOT_FOUND, "session_not_found"));
+    }
+    Ok(Json(serde_json::json!({"session_id": session_id, "deleted": true})))
+}
+
+/// Queue a turn of the conversation. Turns are answered in order: while the
+/// previous turn is queued or running, a new one gets `409 Conflict`.
+pub async fn post_message(
+    State(state): State<AppState>,
+    VerifiedCaller(caller): VerifiedCaller,
+    UrlPath(session_id): UrlPath<String>,
+    Json(message): Json<PostMessage>,
+) -> ApiResult {
+    let session = owned_session(&state, &session_id, caller.as_ref()).await?;
+
+    let request = TaskRequest {
+        agent_id: session.agent_id,
+        prompt: message.content,
+        context: serde_json::Value::Null,
+        priority: message.priority,
+        timeout_secs: message.timeout_secs,
+        session_id: Some(session_id.clone()),
+        caller,
+    };
+    let task_id = enqueue_task(&state, &request).await?;
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "task_id": task_id,
+        "status": TaskState::Queued,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{SqliteTaskStore, TaskStore};
+
+    fn message(role: Role, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_truncate_history_keeps_most_recent() {
+        // 40 characters: 10 tokens + 4 of framing each
+        let long = "x".repeat(40);
+        let messages = vec![
+            message(Role::User, &long),
+            message(Role::Assistant, &long),
+            message(Role::User, &long),
+        ];
+
+        assert_eq!(truncate_history(&messages, 100).len(), 3);
+        let kept = truncate_history(&messages, 30);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].role, Role::Assistant);
+        assert!(truncate_history(&messages, 10).is_empty());
+    }
+
+    #[test]
+    fn test_history_for_turn_reserves_room_for_reply() {
+        let session = Session {
+            session_id: "s".to_string(),
+            agent_id: "summarizer-1".to_string(),
+            owner: None,
+            system_prompt: None,
+            messages: vec![message(Role::User, &"x".repeat(40)), message(Role::Assistant, "ok")],
+            created_at: 0,
+        };
+
+        // 40-token window: 30 usable, 5 for the prompt, 5 for "ok", 14 for the first message;
+        // at 28 only 16 are left for history
+        assert_eq!(history_for_turn(&session, None, "hi", 40).len(), 2);
+        assert_eq!(history_for_turn(&session, None, "hi", 28).len(), 1);
+    }
+
+    async fn exercise(store: &dyn SessionStore, tasks: &dyn TaskStore) {
+        let session = Session {
+            session_id: "session-1".to_string(),
+            agent_id: "summarizer-1".to_string(),
+            owner: Some("user-123".to_string()),
+            system_prompt: Some("Be brief.".to_string()),
+            messages: Vec::new(),
+            created_at: now(),
+        };
+        store.create(&session).await.unwrap();
+
+        for task_id in ["task-1", "task-2", "task-3"] {
+            let request = TaskRequest {
+                agent_id: "summarizer-1".to_string(),
+                prompt: "Hi".to_string(),
+                context: serde_json::Value::Null,
+                priority: 0,
+                timeout_secs: None,
+                session_id: None,
+                caller: None,
+            };
+            tasks.enqueue(task_id, &request).await.unwrap();
+            tasks.claim_next(&[]).await.unwrap();
+        }
+        let turn = [message(Role::User, "Hi"), message(Role::Assistant, "Hello!")];
+        let result = serde_json::json!({"response": "Hello!"});
+        let outcome = store.complete_turn("task-1", "session-1", &turn, &result).await.unwrap();
+        assert_eq!(outcome, TurnOutcome::Completed);
+        let task = tasks.get("task-1").await.unwrap().unwrap();
+        assert_eq!((task.status, task.result), (TaskState::Completed, Some(result.clone())));
+        // Finished tasks cannot add their turn again
+        let outcome = store.complete_turn("task-1", "session-1", &turn, &result).await.unwrap();
+        assert_eq!(outcome, TurnOutcome::TaskNotRunning);
+
+        // A cancelled turn, or one whose session is gone, changes nothing
+        tasks.cancel("task-2").await.unwrap();
+        let outcome = store.complete_turn("task-2", "session-1", &turn, &result).await.unwrap();
+        assert_eq!(outcome, TurnOutcome::TaskNotRunning);
+        let outcome = store.complete_turn("task-3", "session-9", &turn, &result).await.unwrap();
+        assert_eq!(outcome, TurnOutcome::SessionNotFound);
+        assert_eq!(tasks.get("task-3").await.unwrap().unwrap().status, TaskState::Running);
+
+        let stored = store.get("session-1").await.unwrap().unwrap();
+        assert_eq!(stored.owner.as_deref(), Some("user-123"));
+        assert_eq!(stored.system_prompt.as_deref(), Some("Be brief."));
+        assert_eq!(stored.messages, turn);
+
+        assert!(store.delete("session-1").await.unwrap());
+        assert!(store.get("session-1").await.unwrap().is_none());
+        assert!(!store.delete("session-1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        let tasks = Arc::new(InMemoryTaskStore::default());
+        exercise(&InMemorySessionStore::new(tasks.clone()), tasks.as_ref()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let path = std::env::temp_dir().join(format!("lornu-worker-sessions-{}.db", Uuid::new_v4()));
+        let tasks = SqliteTaskStore::open(&path).unwrap();
+        exercise(&SqliteSessionStore::open(&path).unwrap(), &tasks).await;
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,885 @@
+//! Task Store
+//!
+//! Durable record of every task the worker has accepted. Queued and running
+//! tasks survive restarts: tasks still marked `running` when the worker
+//! starts were interrupted (crash, eviction, expired shutdown grace) and are
+//! put back in the queue. Finished tasks are kept for a retention window so
+//! clients can collect their results, then purged.
+//!
+//! SQLite is the default backend; the in-memory store is for tests and local
+//! runs where losing tasks on restart is fine.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sessions::{InMemorySessionStore, SessionStore, SqliteSessionStore};
+use crate::TaskRequest;
+
+/// Database location when `WORKER_TASK_DB` is unset.
+pub const DEFAULT_DB_PATH: &str = "/tmp/lornu-agent-worker-tasks.db";
+
+/// How long finished tasks are kept when `WORKER_TASK_RETENTION_SECS` is unset.
+pub const DEFAULT_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+/// Page size for task listings when no `limit` is given.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Largest page a listing returns.
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Lifecycle of a task
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TaskState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskState::Queued => "queued",
+            TaskState::Running => "running",
+            TaskState::Completed => "completed",
+            TaskState::Failed => "failed",
+            TaskState::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "queued" => Ok(TaskState::Queued),
+            "running" => Ok(TaskState::Running),
+            "completed" => Ok(TaskState::Completed),
+            "failed" => Ok(TaskState::Failed),
+            "cancelled" => Ok(TaskState::Cancelled),
+            other => anyhow::bail!("Unknown task state '{}'", other),
+        }
+    }
+}
+
+/// A task as reported by the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub task_id: String,
+    pub agent_id: String,
+    pub status: TaskState,
+    pub priority: i32,
+    pub result: Option<serde_json::Value>,
+    /// Unix seconds
+    pub created_at: u64,
+    /// Unix seconds, once completed, failed or cancelled
+    pub finished_at: Option<u64>,
+}
+
+/// A task waiting to be executed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedTask {
+    pub task_id: String,
+    pub request: TaskRequest,
+}
+
+/// Which tasks a listing returns
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub agent_id: Option<String>,
+    pub status: Option<TaskState>,
+    /// Continue after the task this cursor points at
+    pub cursor: Option<Cursor>,
+    pub limit: usize,
+}
+
+/// Position in a task listing
+///
+/// Opaque to clients; internally the task's insertion sequence number, so
+/// pages stay stable while new tasks are submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor(u64);
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:x}", self.0)
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(Cursor)
+    }
+}
+
+/// One page of a task listing
+#[derive(Debug, Clone)]
+pub struct TaskPage {
+    pub tasks: Vec<TaskStatus>,
+    /// Set when more tasks match the filter
+    pub next_cursor: Option<Cursor>,
+}
+
+/// Result of queueing a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Enqueued {
+    Queued,
+    /// Not queued: another turn of the task's session, with this id, is
+    /// still queued or running
+    SessionBusy(String),
+}
+
+/// Result of a cancellation request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancelled {
+    /// The task had not started and never will
+    Queued,
+    /// The task was running; its executor must be stopped
+    Running,
+    /// The task had already finished in this state
+    AlreadyFinished(TaskState),
+}
+
+/// Persistence for the task queue and task results
+#[async_trait]
+pub trait TaskStore: Send + Sync {
+    /// Record a new task as queued. A session runs one turn at a time, so a
+    /// turn is refused while an earlier one of the same session is unfinished.
+    async fn enqueue(&self, task_id: &str, request: &TaskRequest) -> Result<Enqueued>;
+
+    /// Mark the next queued task as running and return it: highest priority
+    /// first, oldest first within a priority. Tasks of agents in `skip_agents`
+    /// (at their concurrency limit) are passed over.
+    async fn claim_next(&self, skip_agents: &[String]) -> Result<Option<QueuedTask>>;
+
+    /// Record the outcome of a running task. Tasks cancelled meanwhile keep
+    /// their cancelled state.
+    async fn finish(&self, task_id: &str, state: TaskState, result: serde_json::Value) -> Result<()>;
+
+    /// Put tasks left `running` back in the queue; returns how many.
+    async fn requeue_running(&self) -> Result<usize>;
+
+    /// Delete tasks that finished before `cutoff` (Unix seconds); returns how many.
+    async fn purge_finished(&self, cutoff: u64) -> Result<usize>;
+
+    /// Look up a single task.
+    async fn get(&self, task_id: &str) -> Result<Option<TaskStatus>>;
+
+    /// Mark a queued or running task as cancelled; `None` if it does not exist.
+    async fn cancel(&self, task_id: &str) -> Result<Option<Cancelled>>;
+
+    /// Tasks matching `filter`, oldest first.
+    async fn list(&self, filter: &TaskFilter) -> Result<TaskPage>;
+
+    /// Number of queued tasks.
+    async fn queued(&self) -> Result<usize>;
+}
+
+/// Current time in Unix seconds.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn cancellation(state: TaskState) -> Cancelled {
+    match state {
+        TaskState::Queued => Cancelled::Queued,
+        TaskState::Running => Cancelled::Running,
+        finished => Cancelled::AlreadyFinished(finished),
+    }
+}
+
+/// A task and the request it was submitted with
+#[derive(Debug, Clone)]
+struct StoredTask {
+    seq: u64,
+    status: TaskStatus,
+    request: TaskRequest,
+}
+
+/// Task store that lives and dies with the process
+#[derive(Default)]
+pub struct InMemoryTaskStore {
+    tasks: Mutex<Vec<StoredTask>>,
+    next_seq: std::sync::atomic::AtomicU64,
+}
+
+impl InMemoryTaskStore {
+    fn tasks(&self) -> std::sync::MutexGuard<'_, Vec<StoredTask>> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hold `task_id` while it is running: until the guard is dropped, the
+    /// task can be neither cancelled nor finished by anyone else.
+    pub fn lock_running(&self, task_id: &str) -> Option<RunningTask<'_>> {
+        let tasks = self.tasks();
+        let index = tasks
+            .iter()
+            .position(|t| t.status.task_id == task_id && t.status.status == TaskState::Running)?;
+        Some(RunningTask { tasks, index })
+    }
+}
+
+/// A running task of an [`InMemoryTaskStore`], held
+pub struct RunningTask<'a> {
+    tasks: std::sync::MutexGuard<'a, Vec<StoredTask>>,
+    index: usize,
+}
+
+impl RunningTask<'_> {
+    /// Record the task as completed with `result`.
+    pub fn complete(mut self, result: serde_json::Value) {
+        let task = &mut self.tasks[self.index].status;
+        task.status = TaskState::Completed;
+        task.result = Some(result);
+        task.finished_at = Some(now());
+    }
+}
+
+#[async_trait]
+impl TaskStore for InMemoryTaskStore {
+    async fn enqueue(&self, task_id: &str, request: &TaskRequest) -> Result<Enqueued> {
+        let mut tasks = self.tasks();
+        if let Some(session_id) = &request.session_id {
+            let busy = tasks.iter().find(|t| {
+                t.request.session_id.as_ref() == Some(session_id)
+                    && matches!(t.status.status, TaskState::Queued | TaskState::Running)
+            });
+            if let Some(turn) = busy {
+                return Ok(Enqueued::SessionBusy(turn.status.task_id.clone()));
+            }
+        }
+
+        let seq = self.next_seq.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        tasks.push(StoredTask {
+            seq,
+            status: TaskStatus {
+                task_id: task_id.to_string(),
+                agent_id: request.agent_id.clone(),
+                status: TaskState::Queued,
+                priority: request.priority,
+                result: None,
+                created_at: now(),
+                finished_at: None,
+            },
+            request: request.clone(),
+        });
+        Ok(Enqueued::Queued)
+    }
+
+    async fn claim_next(&self, skip_agents: &[String]) -> Result<Option<QueuedTask>> {
+        let mut tasks = self.tasks();
+        let next = tasks
+            .iter_mut()
+            .filter(|t| t.status.status == TaskState::Queued && !skip_agents.contains(&t.status.agent_id))
+            .min_by_key(|t| (std::cmp::Reverse(t.status.priority), t.seq));
+        let Some(task) = next else {
+            return Ok(None);
+        };
+        task.status.status = TaskState::Running;
+        Ok(Some(QueuedTask {
+            task_id: task.status.task_id.clone(),
+            request: task.request.clone(),
+        }))
+    }
+
+    async fn finish(&self, task_id: &str, state: TaskState, result: serde_json::Value) -> Result<()> {
+        let mut tasks = self.tasks();
+        let running = tasks
+            .iter_mut()
+            .find(|t| t.status.task_id == task_id && t.status.status == TaskState::Running);
+        if let Some(task) = running {
+            task.status.status = state;
+            task.status.result = Some(result);
+            task.status.finished_at = Some(now());
+        }
+        Ok(())
+    }
+
+    async fn requeue_running(&self) -> Result<usize> {
+        let mut requeued = 0;
+        for task in self.tasks().iter_mut().filter(|t| t.status.status == TaskState::Running) {
+            task.status.status = TaskState::Queued;
+            requeued += 1;
+        }
+        Ok(requeued)
+    }
+
+    async fn purge_finished(&self, cutoff: u64) -> Result<usize> {
+        let mut tasks = self.tasks();
+        let before = tasks.len();
+        tasks.retain(|t| t.status.finished_at.is_none_or(|at| at >= cutoff));
+        Ok(before - tasks.len())
+    }
+
+    async fn get(&self, task_id: &str) -> Result<Option<TaskStatus>> {
+        Ok(self
+            .tasks()
+            .iter()
+            .find(|t| t.status.task_id == task_id)
+            .map(|t| t.status.clone()))
+    }
+
+    async fn cancel(&self, task_id: &str) -> Result<Option<Cancelled>> {
+        let mut tasks = self.tasks();
+        let Some(task) = tasks.iter_mut().find(|t| t.status.task_id == task_id) else {
+            return Ok(None);
+        };
+        let outcome = cancellation(task.status.status);
+        if !matches!(outcome, Cancelled::AlreadyFinished(_)) {
+            task.status.status = TaskState::Cancelled;
+            task.status.finished_at = Some(now());
+        }
+        Ok(Some(outcome))
+    }
+
+    async fn list(&self, filter: &TaskFilter) -> Result<TaskPage> {
+        let after = filter.cursor.map_or(0, |c| c.0);
+        let tasks = self.tasks();
+        let mut matching = tasks.iter().filter(|t| {
+            t.seq > after
+                && filter.agent_id.as_ref().is_none_or(|a| &t.status.agent_id == a)
+                && filter.status.is_none_or(|s| t.status.status == s)
+        });
+
+        let page: Vec<&StoredTask> = matching.by_ref().take(filter.limit).collect();
+        let next_cursor = match (matching.next(), page.last()) {
+            (Some(_), Some(last)) => Some(Cursor(last.seq)),
+            _ => None,
+        };
+        Ok(TaskPage {
+            tasks: page.into_iter().map(|t| t.status.clone()).collect(),
+            next_cursor,
+        })
+    }
+
+    async fn queued(&self) -> Result<usize> {
+        Ok(self.tasks().iter().filter(|t| t.status.status == TaskState::Queued).count())
+    }
+}
+
+/// Task store backed by an embedded SQLite database
+pub struct SqliteTaskStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteTaskStore {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("Failed to open task database {}", path.display()))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             CREATE TABLE IF NOT EXISTS tasks (
+                 seq         INTEGER PRIMARY KEY AUTOINCREMENT,
+                 task_id     TEXT NOT NULL UNIQUE,
+                 agent_id    TEXT NOT NULL,
+                 request     TEXT NOT NULL,
+                 status      TEXT NOT NULL,
+                 priority    INTEGER NOT NULL DEFAULT 0,
+                 result      TEXT,
+                 created_at  INTEGER NOT NULL,
+                 finished_at INTEGER
+             );
+             CREATE INDEX IF NOT EXISTS tasks_status ON tasks (status, seq);",
+        )
+        .context("Failed to initialise task database")?;
+
+        // Databases created before task priorities existed
+        let has_priority = conn
+            .prepare("SELECT 1 FROM pragma_table_info('tasks') WHERE name = 'priority'")?
+            .exists([])?;
+        if !has_priority {
+            conn.execute_batch("ALTER TABLE tasks ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;")
+                .context("Failed to migrate task database")?;
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run a query on the blocking pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl TaskStore for SqliteTaskStore {
+    async fn enqueue(&self, task_id: &str, request: &TaskRequest) -> Result<Enqueued> {
+        let task_id = task_id.to_string();
+        let agent_id = request.agent_id.clone();
+        let priority = request.priority;
+        let session_id = request.session_id.clone();
+        let request = serde_json::to_string(request)?;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            if let Some(session_id) = session_id {
+                let busy = tx
+                    .query_row(
+                        "SELECT task_id FROM tasks
+                         WHERE status IN (?1, ?2) AND json_extract(request, '$.session_id') = ?3
+                         LIMIT 1",
+                        params![TaskState::Queued.as_str(), TaskState::Running.as_str(), session_id],
+                        |row| row.get::<_, String>(0),
+                    )
+                    .optional()?;
+                if let Some(turn) = busy {
+                    return Ok(Enqueued::SessionBusy(turn));
+                }
+            }
+            tx.execute(
+                "INSERT INTO tasks (task_id, agent_id, request, status, priority, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![task_id, agent_id, request, TaskState::Queued.as_str(), priority, now() as i64],
+            )?;
+            tx.commit()?;
+            Ok(Enqueued::Queued)
+        })
+        .await
+    }
+
+    async fn claim_next(&self, skip_agents: &[String]) -> Result<Option<QueuedTask>> {
+        let skip_agents = serde_json::to_string(skip_agents)?;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let next = tx
+                .query_row(
+                    "SELECT task_id, request FROM tasks
+                     WHERE status = ?1 AND agent_id NOT IN (SELECT value FROM json_each(?2))
+                     ORDER BY priority DESC, seq LIMIT 1",
+                    params![TaskState::Queued.as_str(), skip_agents],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            let Some((task_id, request)) = next else {
+                return Ok(None);
+            };
+            tx.execute(
+                "UPDATE tasks SET status = ?1 WHERE task_id = ?2",
+                params![TaskState::Running.as_str(), task_id],
+            )?;
+            tx.commit()?;
+
+            let request = serde_json::from_str(&request)
+                .with_context(|| format!("Invalid stored request for task {}", task_id))?;
+            Ok(Some(QueuedTask { task_id, request }))
+        })
+        .await
+    }
+
+    async fn finish(&self, task_id: &str, state: TaskState, result: serde_json::Value) -> Result<()> {
+        let task_id = task_id.to_string();
+        let result = serde_json::to_string(&result)?;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE tasks SET status = ?1, result = ?2, finished_at = ?3 WHERE task_id = ?4 AND status = ?5",
+                params![state.as_str(), result, now() as i64, task_id, TaskState::Running.as_str()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn requeue_running(&self) -> Result<usize> {
+        self.with_conn(|conn| {
+            Ok(conn.execute(
+                "UPDATE tasks SET status = ?1 WHERE status = ?2",
+                params![TaskState::Queued.as_str(), TaskState::Running.as_str()],
+            )?)
+        })
+        .await
+    }
+
+    async fn purge_finished(&self, cutoff: u64) -> Result<usize> {
+        self.with_conn(move |conn| {
+            Ok(conn.execute(
+                "DELETE FROM tasks WHERE finished_at IS NOT NULL AND finished_at < ?1",
+                params![cutoff as i64],
+            )?)
+        })
+        .await
+    }
+
+    async fn get(&self, task_id: &str) -> Result<Option<TaskStatus>> {
+        let task_id = task_id.to_string();
+        self.with_conn(move |conn| {
+            let row = conn
+                .query_row(
+                    &format!("SELECT {} FROM tasks WHERE task_id = ?1", STATUS_COLUMNS),
+                    params![task_id],
+                    status_row,
+                )
+                .optional()?;
+            row.map(|(_, status)| status.try_into()).transpose()
+        })
+        .await
+    }
+
+    async fn cancel(&self, task_id: &str) -> Result<Option<Cancelled>> {
+        let task_id = task_id.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let state = tx
+                .query_row("SELECT status FROM tasks WHERE task_id = ?1", params![task_id], |row| {
+                    row.get::<_, String>(0)
+                })
+                .optional()?;
+            let Some(state) = state else {
+                return Ok(None);
+            };
+
+            let outcome = cancellation(TaskState::parse(&state)?);
+            if !matches!(outcome, Cancelled::AlreadyFinished(_)) {
+                tx.execute(
+                    "UPDATE tasks SET status = ?1, finished_at = ?2 WHERE task_id = ?3",
+                    params![TaskState::Cancelled.as_str(), now() as i64, task_id],
+                )?;
+            }
+            tx.commit()?;
+            Ok(Some(outcome))
+        })
+        .await
+    }
+
+    async fn list(&self, filter: &TaskFilter) -> Result<TaskPage> {
+        let filter = filter.clone();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM tasks
+                 WHERE seq > ?1 AND (?2 IS NULL OR agent_id = ?2) AND (?3 IS NULL OR status = ?3)
+                 ORDER BY seq LIMIT ?4",
+                STATUS_COLUMNS
+            ))?;
+            let rows = stmt.query_map(
+                params![
+                    filter.cursor.map_or(0, |c| c.0) as i64,
+                    filter.agent_id,
+                    filter.status.map(|s| s.as_str()),
+                    // One extra row tells whether there is another page
+                    filter.limit as i64 + 1,
+                ],
+                status_row,
+            )?;
+
+            let mut tasks = Vec::new();
+            let mut last_seq = 0;
+            let mut more = false;
+            for row in rows {
+                let (seq, status) = row?;
+                if tasks.len() == filter.limit {
+                    more = true;
+                    break;
+                }
+                last_seq = seq;
+                tasks.push(status.try_into()?);
+            }
+            Ok(TaskPage {
+                tasks,
+                next_cursor: more.then_some(Cursor(last_seq as u64)),
+            })
+        })
+        .await
+    }
+
+    async fn queued(&self) -> Result<usize> {
+        self.with_conn(|conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM tasks WHERE status = ?1",
+                params![TaskState::Queued.as_str()],
+                |row| row.get(0),
+            )?;
+            Ok(count as usize)
+        })
+        .await
+    }
+}
+
+/// Columns read by [`status_row`].
+const STATUS_COLUMNS: &str = "seq, task_id, agent_id, status, priority, result, created_at, finished_at";
+
+/// A task row as stored, before its JSON and status are parsed
+struct StatusRow {
+    task_id: String,
+    agent_id: String,
+    status: String,
+    priority: i32,
+    result: Option<String>,
+    created_at: i64,
+    finished_at: Option<i64>,
+}
+
+fn status_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<(i64, StatusRow)> {
+    Ok((
+        row.get(0)?,
+        StatusRow {
+            task_id: row.get(1)?,
+            agent_id: row.get(2)?,
+            status: row.get(3)?,
+            priority: row.get(4)?,
+            result: row.get(5)?,
+            created_at: row.get(6)?,
+            finished_at: row.get(7)?,
+        },
+    ))
+}
+
+impl TryFrom<StatusRow> for TaskStatus {
+    type Error = anyhow::Error;
+
+    fn try_from(row: StatusRow) -> Result<Self> {
+        Ok(TaskStatus {
+            task_id: row.task_id,
+            agent_id: row.agent_id,
+            status: TaskState::parse(&row.status)?,
+            priority: row.priority,
+            result: row.result.map(|r| serde_json::from_str(&r)).transpose()?,
+            created_at: row.created_at as u64,
+            finished_at: row.finished_at.map(|at| at as u64),
+        })
+    }
+}
+
+/// Open the task and session stores selected by the environment. Sessions
+/// live with the tasks, so a turn and its task can complete in one step.
+///
+/// - `WORKER_TASK_STORE`: `sqlite` (default) or `memory`
+/// - `WORKER_TASK_DB`: SQLite database path (default: [`DEFAULT_DB_PATH`])
+pub fn from_env() -> Result<(Arc<dyn TaskStore>, Arc<dyn SessionStore>)> {
+    match std::env::var("WORKER_TASK_STORE").unwrap_or_default().as_str() {
+        "" | "sqlite" => {
+            let path = std::env::var("WORKER_TASK_DB").unwrap_or_else(|_| DEFAULT_DB_PATH.to_string());
+            let tasks = SqliteTaskStore::open(Path::new(&path))?;
+            Ok((Arc::new(tasks), Arc::new(SqliteSessionStore::open(Path::new(&path))?)))
+        }
+        "memory" => {
+            let tasks = Arc::new(InMemoryTaskStore::default());
+            Ok((tasks.clone(), Arc::new(InMemorySessionStore::new(tasks))))
+        }
+        other => anyhow::bail!("Unknown WORKER_TASK_STORE '{}' (expected sqlite or memory)", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(agent_id: &str) -> TaskRequest {
+        TaskRequest {
+            agent_id: agent_id.to_string(),
+            prompt: "Summarize the release notes".to_string(),
+            context: serde_json::Value::Null,
+            priority: 0,
+            timeout_secs: None,
+            session_id: None,
+            caller: Some(crate::auth::Caller {
+                subject: "user-123".to_string(),
+                user: "alice".to_string(),
+                tenant: None,
+                scopes: vec!["dns:write".to_string()],
+            }),
+        }
+    }
+
+    fn db_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("lornu-worker-tasks-{}.db", uuid::Uuid::new_v4()))
+    }
+
+    async fn exercise(store: &dyn TaskStore) {
+        store.enqueue("task-1", &request("summarizer-1")).await.unwrap();
+        store.enqueue("task-2", &request("coder-1")).await.unwrap();
+        assert_eq!(store.queued().await.unwrap(), 2);
+
+        let claimed = store.claim_next(&[]).await.unwrap().unwrap();
+        assert_eq!(claimed.task_id, "task-1");
+        assert_eq!(claimed.request.agent_id, "summarizer-1");
+        assert_eq!(claimed.request.caller, request("summarizer-1").caller);
+
+        store
+            .finish("task-1", TaskState::Completed, serde_json::json!({"response": "ok"}))
+            .await
+            .unwrap();
+        assert_eq!(store.claim_next(&[]).await.unwrap().unwrap().task_id, "task-2");
+        assert!(store.claim_next(&[]).await.unwrap().is_none());
+
+        let tasks = store.list(&all()).await.unwrap().tasks;
+        assert_eq!(tasks[0].status, TaskState::Completed);
+        assert_eq!(tasks[0].result, Some(serde_json::json!({"response": "ok"})));
+        assert!(tasks[0].finished_at.is_some());
+        assert_eq!(tasks[1].status, TaskState::Running);
+
+        // Only finished tasks are purged
+        assert_eq!(store.purge_finished(now() + 1).await.unwrap(), 1);
+        assert_eq!(store.list(&all()).await.unwrap().tasks.len(), 1);
+    }
+
+    async fn exercise_session_turns(store: &dyn TaskStore) {
+        let turn = TaskRequest {
+            session_id: Some("session-1".to_string()),
+            ..request("summarizer-1")
+        };
+        assert_eq!(store.enqueue("task-1", &turn).await.unwrap(), Enqueued::Queued);
+        // Other sessions and session-less tasks are unaffected
+        let other = TaskRequest {
+            session_id: Some("session-2".to_string()),
+            ..request("summarizer-1")
+        };
+        assert_eq!(store.enqueue("task-2", &other).await.unwrap(), Enqueued::Queued);
+        assert_eq!(store.enqueue("task-3", &request("summarizer-1")).await.unwrap(), Enqueued::Queued);
+
+        let busy = Enqueued::SessionBusy("task-1".to_string());
+        assert_eq!(store.enqueue("task-4", &turn).await.unwrap(), busy);
+        store.claim_next(&[]).await.unwrap();
+        assert_eq!(store.enqueue("task-4", &turn).await.unwrap(), busy);
+
+        store.finish("task-1", TaskState::Completed, serde_json::json!({})).await.unwrap();
+        assert_eq!(store.enqueue("task-4", &turn).await.unwrap(), Enqueued::Queued);
+        assert!(store.get("task-4").await.unwrap().is_some());
+    }
+
+    fn all() -> TaskFilter {
+        TaskFilter {
+            limit: DEFAULT_PAGE_SIZE,
+            ..Default::default()
+        }
+    }
+
+    async fn exercise_cancel_and_list(store: &dyn TaskStore) {
+        for n in 1..=5 {
+            let agent = if n % 2 == 0 { "coder-1" } else { "summarizer-1" };
+            store.enqueue(&format!("task-{}", n), &request(agent)).await.unwrap();
+        }
+        store.claim_next(&[]).await.unwrap();
+
+        assert_eq!(store.cancel("task-1").await.unwrap(), Some(Cancelled::Running));
+        assert_eq!(store.cancel("task-2").await.unwrap(), Some(Cancelled::Queued));
+        assert_eq!(
+            store.cancel("task-2").await.unwrap(),
+            Some(Cancelled::AlreadyFinished(TaskState::Cancelled))
+        );
+        assert_eq!(store.cancel("task-9").await.unwrap(), None);
+
+        // A cancelled task is skipped by the queue and keeps its state when
+        // the aborted executor reports back
+        store.finish("task-1", TaskState::Failed, serde_json::json!({})).await.unwrap();
+        assert_eq!(store.get("task-1").await.unwrap().unwrap().status, TaskState::Cancelled);
+        assert_eq!(store.claim_next(&[]).await.unwrap().unwrap().task_id, "task-3");
+        assert!(store.get("task-9").await.unwrap().is_none());
+
+        let summarizers = TaskFilter {
+            agent_id: Some("summarizer-1".to_string()),
+            limit: 2,
+            ..Default::default()
+        };
+        let first = store.list(&summarizers).await.unwrap();
+        let ids: Vec<_> = first.tasks.iter().map(|t| t.task_id.as_str()).collect();
+        assert_eq!(ids, ["task-1", "task-3"]);
+
+        let second = store
+            .list(&TaskFilter {
+                cursor: first.next_cursor,
+                ..summarizers
+            })
+            .await
+            .unwrap();
+        let ids: Vec<_> = second.tasks.iter().map(|t| t.task_id.as_str()).collect();
+        assert_eq!(ids, ["task-5"]);
+        assert!(second.next_cursor.is_none());
+
+        let queued = TaskFilter {
+            status: Some(TaskState::Queued),
+            ..all()
+        };
+        let ids: Vec<_> = store.list(&queued).await.unwrap().tasks.into_iter().map(|t| t.task_id).collect();
+        assert_eq!(ids, ["task-4", "task-5"]);
+    }
+
+    async fn exercise_priorities(store: &dyn TaskStore) {
+        let urgent = TaskRequest {
+            priority: 10,
+            ..request("summarizer-1")
+        };
+        store.enqueue("task-1", &request("summarizer-1")).await.unwrap();
+        store.enqueue("task-2", &request("coder-1")).await.unwrap();
+        store.enqueue("task-3", &urgent).await.unwrap();
+        store.enqueue("task-4", &urgent).await.unwrap();
+
+        let skip_summarizers = ["summarizer-1".to_string()];
+        assert_eq!(store.claim_next(&[]).await.unwrap().unwrap().task_id, "task-3");
+        assert_eq!(store.claim_next(&skip_summarizers).await.unwrap().unwrap().task_id, "task-2");
+        assert!(store.claim_next(&skip_summarizers).await.unwrap().is_none());
+        assert_eq!(store.claim_next(&[]).await.unwrap().unwrap().task_id, "task-4");
+        assert_eq!(store.claim_next(&[]).await.unwrap().unwrap().task_id, "task-1");
+        assert_eq!(store.get("task-3").await.unwrap().unwrap().priority, 10);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        exercise(&InMemoryTaskStore::default()).await;
+        exercise_cancel_and_list(&InMemoryTaskStore::default()).await;
+        exercise_priorities(&InMemoryTaskStore::default()).await;
+        exercise_session_turns(&InMemoryTaskStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let path = db_path();
+        exercise(&SqliteTaskStore::open(&path).unwrap()).await;
+        let _ = std::fs::remove_file(&path);
+
+        let path = db_path();
+        exercise_cancel_and_list(&SqliteTaskStore::open(&path).unwrap()).await;
+        let _ = std::fs::remove_file(&path);
+
+        let path = db_path();
+        exercise_priorities(&SqliteTaskStore::open(&path).unwrap()).await;
+        let _ = std::fs::remove_file(&path);
+
+        let path = db_path();
+        exercise_session_turns(&SqliteTaskStore::open(&path).unwrap()).await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor(4096);
+        assert_eq!(cursor.to_string().parse::<Cursor>().unwrap(), cursor);
+        assert!("not-a-cursor".parse::<Cursor>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_requeues_interrupted_tasks_after_restart() {
+        let path = db_path();
+        {
+            let store = SqliteTaskStore::open(&path).unwrap();
+            store.enqueue("task-1", &request("summarizer-1")).await.unwrap();
+            store.enqueue("task-2", &request("summarizer-1")).await.unwrap();
+            store.claim_next(&[]).await.unwrap();
+        }
+
+        let store = SqliteTaskStore::open(&path).unwrap();
+        assert_eq!(store.requeue_running().await.unwrap(), 1);
+        assert_eq!(store.queued().await.unwrap(), 2);
+        // The interrupted task keeps its place at the front of the queue
+        assert_eq!(store.claim_next(&[]).await.unwrap().unwrap().task_id, "task-1");
+        let _ = std::fs::remove_file(&path);
+    }
+}
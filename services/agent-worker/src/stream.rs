@@ -0,0 +1,250 @@
+//! Live Task Output
+//!
+//! Text generated by running tasks, streamed to clients as it arrives via
+//! `GET /api/tasks/:id/stream` (server-sent events):
+//!
+//! - `status`: `{"status": "queued" | "running"}` whenever it changes
+//! - `token`: `{"text": "..."}` with generated text; the first `token` after
+//!   connecting carries everything generated so far
+//! - `reset`: `{"text": "..."}` with everything generated so far, replacing
+//!   the text received until now; sent when a slow client fell so far behind
+//!   that tokens were dropped
+//! - `done`: the finished task, as returned by `GET /api/tasks/:id`; the
+//!   stream then ends
+//!
+//! Only running tasks have live output; the final result is stored on the
+//! task as before.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::Stream;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::store::{TaskState, TaskStatus};
+use crate::{api_error, store_error, AppState};
+
+/// Tokens buffered per subscriber before a slow client starts missing some.
+const TOKEN_BUFFER: usize = 1024;
+
+/// How often a queued task is checked for having started.
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Output of a running task
+struct Live {
+    text: String,
+    tokens: broadcast::Sender<String>,
+}
+
+/// Live output of all running tasks, by task id
+#[derive(Default)]
+pub struct TaskStreams {
+    tasks: Mutex<HashMap<String, Live>>,
+}
+
+impl TaskStreams {
+    fn tasks(&self) -> std::sync::MutexGuard<'_, HashMap<String, Live>> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start collecting output for `task_id`; it is discarded when the
+    /// returned handle drops.
+    pub fn register(self: &Arc<Self>, task_id: &str) -> LiveOutput {
+        let (tokens, _) = broadcast::channel(TOKEN_BUFFER);
+        self.tasks().insert(
+            task_id.to_string(),
+            Live {
+                text: String::new(),
+                tokens,
+            },
+        );
+        LiveOutput {
+            streams: self.clone(),
+            task_id: task_id.to_string(),
+        }
+    }
+
+    /// The output so far and a receiver for what follows, if the task is running.
+    fn subscribe(&self, task_id: &str) -> Option<(String, broadcast::Receiver<String>)> {
+        self.tasks()
+            .get(task_id)
+            .map(|live| (live.text.clone(), live.tokens.subscribe()))
+    }
+}
+
+/// Handle a running task publishes its output through
+pub struct LiveOutput {
+    streams: Arc<TaskStreams>,
+    task_id: String,
+}
+
+impl LiveOutput {
+    pub fn push(&self, token: &str) {
+        if let Some(live) = self.streams.tasks().get_mut(&self.task_id) {
+            live.text.push_str(token);
+            // No receivers just means nobody is watching
+            let _ = live.tokens.send(token.to_string());
+        }
+    }
+}
+
+impl Drop for LiveOutput {
+    fn drop(&mut self) {
+        self.streams.tasks().remove(&self.task_id);
+    }
+}
+
+/// `GET /api/tasks/:id/stream`
+pub async fn stream_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(task) = state.store.get(&task_id).await.map_err(store_error)? else {
+        return Err(api_error(StatusCode::NOT_FOUND, "task_not_found"));
+    };
+
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(follow(state, task, tx));
+
+    let events = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv()
+            .await
+            .map(|(name, data)| (Ok(Event::default().event(name).data(data.to_string())), rx))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// An event's name and data
+type Message = (&'static str, serde_json::Value);
+
+/// Feed `tx` with a task's status changes and output until it finishes or
+/// the client disconnects.
+async fn follow(state: AppState, mut task: TaskStatus, tx: mpsc::Sender<Message>) {
+    let mut reported = None;
+    loop {
+        if matches!(
+            task.status,
+            TaskState::Completed | TaskState::Failed | TaskState::Cancelled
+        ) {
+            let _ = tx.send(("done", serde_json::json!(task))).await;
+            return;
+        }
+        if reported != Some(task.status) {
+            reported = Some(task.status);
+            if tx.send(("status", serde_json::json!({"status": task.status}))).await.is_err() {
+                return;
+            }
+        }
+
+        match state.streams.subscribe(&task.task_id) {
+            Some((so_far, tokens)) => {
+                if !so_far.is_empty() && tx.send(("token", serde_json::json!({"text": so_far}))).await.is_err() {
+                    return;
+                }
+                if !relay(&state.streams, &task.task_id, tokens, &tx).await {
+                    return;
+                }
+            }
+            None => {
+                tokio::select! {
+                    _ = tokio::time::sleep(STATUS_POLL_INTERVAL) => {}
+                    _ = tx.closed() => return,
+                }
+            }
+        }
+
+        task = match state.store.get(&task.task_id).await {
+            Ok(Some(task)) => task,
+            // Purged, or the store is failing: nothing more to report
+            _ => return,
+        };
+    }
+}
+
+/// Forward a running task's tokens to `tx` until the task stops running.
+/// Returns `false` if the client disconnected.
+async fn relay(
+    streams: &TaskStreams,
+    task_id: &str,
+    mut tokens: broadcast::Receiver<String>,
+    tx: &mpsc::Sender<Message>,
+) -> bool {
+    loop {
+        let message = match tokens.recv().await {
+            Ok(token) => ("token", serde_json::json!({"text": token})),
+            // Dropped tokens would corrupt the client's text: start it over
+            Err(broadcast::error::RecvError::Lagged(_)) => match streams.subscribe(task_id) {
+                Some((so_far, resubscribed)) => {
+                    tokens = resubscribed;
+                    ("reset", serde_json::json!({"text": so_far}))
+                }
+                None => return true,
+            },
+            Err(broadcast::error::RecvError::Closed) => return true,
+        };
+        if tx.send(message).await.is_err() {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscriber_gets_output_so_far_then_tokens() {
+        let streams = Arc::new(TaskStreams::default());
+        let live = streams.register("task-1");
+        live.push("Hel");
+
+        let (so_far, mut tokens) = streams.subscribe("task-1").unwrap();
+        assert_eq!(so_far, "Hel");
+        live.push("lo");
+        assert_eq!(tokens.recv().await.unwrap(), "lo");
+
+        drop(live);
+        assert!(matches!(tokens.recv().await, Err(broadcast::error::RecvError::Closed)));
+        assert!(streams.subscribe("task-1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lagging_client_gets_reset() {
+        let streams = Arc::new(TaskStreams::default());
+        let live = streams.register("task-1");
+        let (_, tokens) = streams.subscribe("task-1").unwrap();
+
+        // Nothing is read until far more than the buffer has been generated
+        let (tx, mut rx) = mpsc::channel(1);
+        let relay = tokio::spawn({
+            let streams = streams.clone();
+            async move { relay(&streams, "task-1", tokens, &tx).await }
+        });
+        for _ in 0..TOKEN_BUFFER * 2 {
+            live.push("x");
+        }
+        tokio::task::yield_now().await;
+        let text = tokio::spawn(async move {
+            let mut text = String::new();
+            while let Some((name, data)) = rx.recv().await {
+                if name == "reset" {
+                    text.clear();
+                }
+                text.push_str(data["text"].as_str().unwrap());
+            }
+            text
+        });
+        live.push("!");
+        drop(live);
+
+        assert!(relay.await.unwrap());
+        assert_eq!(text.await.unwrap(), format!("{}!", "x".repeat(TOKEN_BUFFER * 2)));
+    }
+}
@@ -0,0 +1,205 @@
+//! Engine Tools
+//!
+//! The engine's Cloudflare DNS and GitHub team tools, wrapped for agents.
+//! Credentials never reach the model or the worker's task results: the
+//! Cloudflare token is fetched from Secret Manager by `CloudflareTool`, and
+//! the GitHub token is read from `GITHUB_TEAM_PAT` by `GitHubTeamTool`.
+//!
+//! - `cloudflare_list_dns_records`, `cloudflare_create_dns_record`: need
+//!   `LORNU_GCP_PROJECT`
+//! - `github_list_teams`, `github_add_team_member`: need `GITHUB_TEAM_ORG`
+//!   and `GITHUB_TEAM_PAT`
+//!
+//! Creating a DNS record needs the caller's [`SCOPE_DNS_WRITE`] and adding a
+//! team member [`SCOPE_GITHUB_TEAMS_WRITE`]; the list tools need no scope.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use lornu_engine::tools::github::{GitHubTeamTool, TeamRole};
+use lornu_engine::CloudflareTool;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use super::{arguments, Tool, ToolRegistry};
+use crate::llm::ToolSpec;
+
+/// Scope a caller needs to create DNS records, as for the engine's API
+pub const SCOPE_DNS_WRITE: &str = "dns:write";
+/// Scope a caller needs to change GitHub team membership
+pub const SCOPE_GITHUB_TEAMS_WRITE: &str = "github:teams:write";
+
+/// Register the engine tools whose credentials are configured.
+pub fn register(registry: &mut ToolRegistry) {
+    match CloudflareTool::new() {
+        Ok(cloudflare) => {
+            let cloudflare = Arc::new(cloudflare);
+            registry.register(Arc::new(ListDnsRecords(cloudflare.clone())));
+            registry.register(Arc::new(CreateDnsRecord(cloudflare)));
+            info!("Cloudflare DNS tools available");
+        }
+        Err(e) => warn!("Cloudflare DNS tools not available: {} (set LORNU_GCP_PROJECT to enable)", e),
+    }
+
+    let Some(org) = std::env::var("GITHUB_TEAM_ORG").ok().filter(|o| !o.is_empty()) else {
+        warn!("GitHub team tools not available (set GITHUB_TEAM_ORG to enable)");
+        return;
+    };
+    match GitHubTeamTool::new(org) {
+        Ok(github) => {
+            let github = Arc::new(github);
+            registry.register(Arc::new(ListTeams(github.clone())));
+            registry.register(Arc::new(AddTeamMember(github)));
+            info!("GitHub team tools available");
+        }
+        Err(e) => warn!("GitHub team tools not available: {}", e),
+    }
+}
+
+const ZONE_ID: &str = "Cloudflare zone ID (default: the worker's CLOUDFLARE_ZONE_ID)";
+
+/// `CloudflareTool::list_dns_records`
+struct ListDnsRecords(Arc<CloudflareTool>);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListDnsRecordsArgs {
+    #[serde(default)]
+    zone_id: Option<String>,
+}
+
+#[async_trait]
+impl Tool for ListDnsRecords {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "cloudflare_list_dns_records".to_string(),
+            description: "List the DNS records in a Cloudflare zone.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {"zone_id": {"type": "string", "description": ZONE_ID}},
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    async fn call(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let args: ListDnsRecordsArgs = arguments("cloudflare_list_dns_records", args)?;
+        let records = self.0.list_dns_records(args.zone_id.as_deref()).await?;
+        Ok(serde_json::json!(records))
+    }
+}
+
+/// `CloudflareTool::create_dns_record`
+struct CreateDnsRecord(Arc<CloudflareTool>);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateDnsRecordArgs {
+    #[serde(default)]
+    zone_id: Option<String>,
+    name: String,
+    content: String,
+    #[serde(default)]
+    proxied: bool,
+}
+
+#[async_trait]
+impl Tool for CreateDnsRecord {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "cloudflare_create_dns_record".to_string(),
+            description: "Create a DNS A record in a Cloudflare zone. Returns the new record's ID.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "zone_id": {"type": "string", "description": ZONE_ID},
+                    "name": {"type": "string", "description": "Record name, e.g. api.lornu.ai"},
+                    "content": {"type": "string", "description": "IPv4 address"},
+                    "proxied": {"type": "boolean", "description": "Proxy through Cloudflare (default: false)"},
+                },
+                "required": ["name", "content"],
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    fn required_scope(&self) -> Option<&'static str> {
+        Some(SCOPE_DNS_WRITE)
+    }
+
+    async fn call(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let args: CreateDnsRecordArgs = arguments("cloudflare_create_dns_record", args)?;
+        let id = self
+            .0
+            .create_dns_record(args.zone_id.as_deref(), &args.name, &args.content, args.proxied)
+            .await?;
+        Ok(serde_json::json!({"id": id}))
+    }
+}
+
+/// `GitHubTeamTool::list_teams`
+struct ListTeams(Arc<GitHubTeamTool>);
+
+#[async_trait]
+impl Tool for ListTeams {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "github_list_teams".to_string(),
+            description: format!("List the slugs of the teams in the {} GitHub organization.", self.0.org),
+            parameters: serde_json::json!({"type": "object", "properties": {}, "additionalProperties": false}),
+        }
+    }
+
+    async fn call(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let _: serde_json::Map<String, serde_json::Value> = arguments("github_list_teams", args)?;
+        Ok(serde_json::json!(self.0.list_teams().await?))
+    }
+}
+
+/// `GitHubTeamTool::add_member_to_team`
+struct AddTeamMember(Arc<GitHubTeamTool>);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddTeamMemberArgs {
+    team_slug: String,
+    username: String,
+    #[serde(default)]
+    role: TeamRole,
+}
+
+#[async_trait]
+impl Tool for AddTeamMember {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "github_add_team_member".to_string(),
+            description: format!(
+                "Add a user to a team in the {} GitHub organization, or change their role.",
+                self.0.org
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "team_slug": {"type": "string", "description": "Team slug, e.g. engineering"},
+                    "username": {"type": "string", "description": "GitHub username"},
+                    "role": {"type": "string", "enum": ["member", "maintainer"], "description": "Default: member"},
+                },
+                "required": ["team_slug", "username"],
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    fn required_scope(&self) -> Option<&'static str> {
+        Some(SCOPE_GITHUB_TEAMS_WRITE)
+    }
+
+    async fn call(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let args: AddTeamMemberArgs = arguments("github_add_team_member", args)?;
+        let result = self
+            .0
+            .add_member_to_team(&args.team_slug, &args.username, args.role)
+            .await?;
+        Ok(serde_json::json!(result))
+    }
+}
@@ -0,0 +1,354 @@
+//! Agent Tools
+//!
+//! Tools agents can call while working on a task. The model asks for a tool
+//! with a structured call, the worker runs it and sends the result back, and
+//! this repeats until the model gives its answer. Each tool describes its
+//! arguments with a JSON schema, and an agent may only call the tools its
+//! `tools` list in the agents file names. Tools that change things also need
+//! a scope from the caller who submitted the task ([`Tool::required_scope`]),
+//! so naming a tool for an agent is necessary but not sufficient.
+//!
+//! With the `engine-tools` feature the engine's Cloudflare DNS and GitHub
+//! team tools are available; see [`engine`].
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::auth::Caller;
+use crate::llm::{LlmBackend, LlmRequest, LlmResponse, Role, ToolCall, ToolSpec, Turn};
+
+#[cfg(feature = "engine-tools")]
+pub mod engine;
+
+/// Rounds of tool calls a task may make before it must answer.
+pub const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Something an agent can do besides generating text
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name, description and argument schema, as shown to the model
+    fn spec(&self) -> ToolSpec;
+
+    /// Scope the task's caller must hold to use this tool; `None` for tools
+    /// that only read.
+    fn required_scope(&self) -> Option<&'static str> {
+        None
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> Result<serde_json::Value>;
+}
+
+/// Decode a tool's arguments, with an error the model can act on.
+#[cfg_attr(not(feature = "engine-tools"), allow(dead_code))]
+pub fn arguments<T: DeserializeOwned>(tool: &str, arguments: serde_json::Value) -> Result<T> {
+    serde_json::from_value(arguments).with_context(|| format!("Invalid arguments for {}", tool))
+}
+
+/// The tools available to agents, by name
+#[derive(Default, Clone)]
+pub struct ToolRegistry {
+    tools: BTreeMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    /// The tools this build and environment provide.
+    pub fn from_env() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = Self::default();
+        #[cfg(feature = "engine-tools")]
+        engine::register(&mut registry);
+        registry
+    }
+
+    #[cfg_attr(not(feature = "engine-tools"), allow(dead_code))]
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.insert(tool.spec().name, tool);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.keys().map(String::as_str).collect()
+    }
+
+    /// Specs of the `allowed` tools that are available to `caller`.
+    pub fn specs(&self, allowed: &[String], caller: Option<&Caller>) -> Vec<ToolSpec> {
+        allowed
+            .iter()
+            .filter_map(|name| self.tools.get(name))
+            .filter(|tool| permitted(tool.as_ref(), caller))
+            .map(|tool| tool.spec())
+            .collect()
+    }
+
+    /// Run `call` if it is one of the `allowed` tools and `caller` holds the
+    /// scope it requires.
+    pub async fn call(
+        &self,
+        allowed: &[String],
+        caller: Option<&Caller>,
+        call: &ToolCall,
+    ) -> Result<serde_json::Value> {
+        if !allowed.contains(&call.name) {
+            anyhow::bail!("Tool {} is not allowed for this agent", call.name);
+        }
+        let tool = self
+            .tools
+            .get(&call.name)
+            .with_context(|| format!("Tool {} is not available", call.name))?;
+        if !permitted(tool.as_ref(), caller) {
+            anyhow::bail!(
+                "Tool {} requires the {} scope, which the caller does not have",
+                call.name,
+                tool.required_scope().unwrap_or_default()
+            );
+        }
+        tool.call(call.arguments.clone()).await
+    }
+}
+
+/// Whether `caller` holds the scope `tool` requires.
+fn permitted(tool: &dyn Tool, caller: Option<&Caller>) -> bool {
+    match tool.required_scope() {
+        Some(scope) => caller.is_some_and(|c| c.has_scope(scope)),
+        None => true,
+    }
+}
+
+/// A tool call made while running a task, as reported in its result
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolTrace {
+    pub name: String,
+    pub arguments: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Run `request` to a final answer, calling the `allowed` tools whenever the
+/// model asks for them, with the permissions of `caller`.
+///
+/// A failing or forbidden call does not fail the task: the error is sent to
+/// the model as the call's result, so it can correct itself.
+pub async fn run(
+    llm: &dyn LlmBackend,
+    registry: &ToolRegistry,
+    allowed: &[String],
+    caller: Option<&Caller>,
+    mut request: LlmRequest,
+) -> Result<(LlmResponse, Vec<ToolTrace>)> {
+    request.tools = registry.specs(allowed, caller);
+    let mut trace = Vec::new();
+
+    for _ in 0..MAX_TOOL_ROUNDS {
+        let resp = llm.complete(&request).await?;
+        if resp.tool_calls.is_empty() {
+            return Ok((resp, trace));
+        }
+
+        request.tool_rounds.push(Turn {
+            tool_calls: resp.tool_calls.clone(),
+            ..Turn::new(Role::Assistant, resp.text)
+        });
+        for call in resp.tool_calls {
+            info!("Calling tool {}", call.name);
+            let outcome = registry.call(allowed, caller, &call).await;
+            let content = match &outcome {
+                Ok(result) => result.to_string(),
+                Err(e) => {
+                    warn!("Tool {} failed: {:#}", call.name, e);
+                    serde_json::json!({"error": format!("{:#}", e)}).to_string()
+                }
+            };
+            request.tool_rounds.push(Turn {
+                tool_call_id: Some(call.id),
+                ..Turn::new(Role::Tool, content)
+            });
+            let (result, error) = match outcome {
+                Ok(result) => (Some(result), None),
+                Err(e) => (None, Some(format!("{:#}", e))),
+            };
+            trace.push(ToolTrace {
+                name: call.name,
+                arguments: call.arguments,
+                result,
+                error,
+            });
+        }
+    }
+
+    anyhow::bail!("No answer after {} rounds of tool calls", MAX_TOOL_ROUNDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Adds `a` and `b`
+    struct Add;
+
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct AddArgs {
+        a: i64,
+        b: i64,
+    }
+
+    #[async_trait]
+    impl Tool for Add {
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: "add".to_string(),
+                description: "Add two numbers".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+                    "required": ["a", "b"],
+                }),
+            }
+        }
+
+        async fn call(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+            let args: AddArgs = arguments("add", args)?;
+            Ok(serde_json::json!(args.a + args.b))
+        }
+    }
+
+    /// Model that makes the calls in `script`, one round each, then answers
+    /// with the last tool result it was sent.
+    struct Scripted {
+        script: Mutex<Vec<ToolCall>>,
+        seen: Mutex<Vec<LlmRequest>>,
+    }
+
+    #[async_trait]
+    impl LlmBackend for Scripted {
+        async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse> {
+            self.seen.lock().unwrap().push(request.clone());
+            let mut script = self.script.lock().unwrap();
+            let (text, tool_calls) = if script.is_empty() {
+                let last = request.tool_rounds.last().map(|t| t.content.clone()).unwrap_or_default();
+                (last, Vec::new())
+            } else {
+                (String::new(), vec![script.remove(0)])
+            };
+            Ok(LlmResponse {
+                text,
+                model: request.model.clone(),
+                tool_calls,
+            })
+        }
+    }
+
+    fn call(id: &str, name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    fn request() -> LlmRequest {
+        LlmRequest {
+            model: "qwen2.5:7b".to_string(),
+            system: None,
+            prompt: "What is 2 + 3?".to_string(),
+            history: Vec::new(),
+            tools: Vec::new(),
+            tool_rounds: Vec::new(),
+            temperature: None,
+            context: serde_json::Value::Null,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_calls_allowed_tools_until_answer() {
+        let mut registry = ToolRegistry::default();
+        registry.register(Arc::new(Add));
+        let llm = Scripted {
+            script: Mutex::new(vec![
+                call("1", "delete_everything", serde_json::json!({})),
+                call("2", "add", serde_json::json!({"a": 2})),
+                call("3", "add", serde_json::json!({"a": 2, "b": 3})),
+            ]),
+            seen: Mutex::default(),
+        };
+        let allowed = vec!["add".to_string(), "missing".to_string()];
+
+        let (resp, trace) = run(&llm, &registry, &allowed, None, request()).await.unwrap();
+        assert_eq!(resp.text, "5");
+        let errors: Vec<_> = trace.iter().map(|t| t.error.is_some()).collect();
+        assert_eq!(errors, [true, true, false]);
+        assert!(trace[0].error.as_deref().unwrap().contains("not allowed"));
+
+        let seen = llm.seen.lock().unwrap();
+        assert_eq!(seen[0].tools.len(), 1);
+        // Each round adds the call and its result
+        assert_eq!(seen[3].tool_rounds.len(), 6);
+        assert_eq!(seen[3].tool_rounds[5].tool_call_id.as_deref(), Some("3"));
+    }
+
+    /// `Add`, but only for callers with the `math:write` scope
+    struct ScopedAdd;
+
+    #[async_trait]
+    impl Tool for ScopedAdd {
+        fn spec(&self) -> ToolSpec {
+            Add.spec()
+        }
+
+        fn required_scope(&self) -> Option<&'static str> {
+            Some("math:write")
+        }
+
+        async fn call(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+            Add.call(args).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scoped_tools_need_the_callers_scope() {
+        let mut registry = ToolRegistry::default();
+        registry.register(Arc::new(ScopedAdd));
+        let allowed = vec!["add".to_string()];
+        let add = call("1", "add", serde_json::json!({"a": 2, "b": 3}));
+        let mut caller = Caller {
+            subject: "user-123".to_string(),
+            user: "alice".to_string(),
+            tenant: None,
+            scopes: vec!["math:read".to_string()],
+        };
+
+        for caller in [None, Some(&caller)] {
+            assert!(registry.specs(&allowed, caller).is_empty());
+            let err = registry.call(&allowed, caller, &add).await.unwrap_err();
+            assert!(err.to_string().contains("math:write"));
+        }
+
+        caller.scopes.push("math:write".to_string());
+        assert_eq!(registry.specs(&allowed, Some(&caller)).len(), 1);
+        assert_eq!(registry.call(&allowed, Some(&caller), &add).await.unwrap(), 5);
+        // The scope does not stand in for the agent's tool list
+        assert!(registry.call(&[], Some(&caller), &add).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_gives_up_after_max_rounds() {
+        let mut registry = ToolRegistry::default();
+        registry.register(Arc::new(Add));
+        let script = (0..MAX_TOOL_ROUNDS + 1)
+            .map(|i| call(&i.to_string(), "add", serde_json::json!({"a": 1, "b": 1})))
+            .collect();
+        let llm = Scripted {
+            script: Mutex::new(script),
+            seen: Mutex::default(),
+        };
+
+        assert!(run(&llm, &registry, &["add".to_string()], None, request()).await.is_err());
+    }
+}
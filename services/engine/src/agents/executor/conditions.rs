@@ -0,0 +1,171 @@
+//! Claim Conditions
+//!
+//! Crossplane status conditions for claims, and the errors reported by
+//! claim operations (invalid specs or names, tenant checks, readiness timeouts).
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+/// A status condition reported by Crossplane on a claim (e.g. `Ready`, `Synced`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimCondition {
+    /// Condition type (`Ready`, `Synced`, ...)
+    #[serde(rename = "type")]
+    pub condition_type: String,
+    /// `True`, `False` or `Unknown`
+    pub status: String,
+    /// Machine-readable reason (e.g. `ReconcileError`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Human-readable message from the composition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// When the condition last changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_transition_time: Option<String>,
+}
+
+impl ClaimCondition {
+    /// Whether this condition has status `True`.
+    pub fn is_true(&self) -> bool {
+        self.status == "True"
+    }
+}
+
+impl fmt::Display for ClaimCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.condition_type, self.status)?;
+        match (&self.reason, &self.message) {
+            (Some(reason), Some(message)) if !message.is_empty() => {
+                write!(f, " ({}: {})", reason, message)
+            }
+            (Some(reason), _) => write!(f, " ({})", reason),
+            (None, Some(message)) if !message.is_empty() => write!(f, " ({})", message),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether the conditions include `Ready=True`.
+pub fn is_ready(conditions: &[ClaimCondition]) -> bool {
+    conditions
+        .iter()
+        .any(|c| c.condition_type == "Ready" && c.is_true())
+}
+
+/// Summarise conditions for logs and error messages.
+pub fn describe(conditions: &[ClaimCondition]) -> String {
+    if conditions.is_empty() {
+        return "no conditions reported".to_string();
+    }
+
+    conditions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Errors that can occur during claim operations
+#[derive(Debug, Error)]
+pub enum ClaimError {
+    /// A spec field failed validation
+    #[error("Invalid claim spec: {0}")]
+    InvalidSpec(String),
+
+    /// A user, agent, tenant or namespace name is not a valid RFC 1123 label
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+
+    /// The request did not name a tenant and no default tenant is configured
+    #[error("No tenant specified and no default tenant configured")]
+    MissingTenant,
+
+    /// The tenant has no namespace mapping
+    #[error("Unknown tenant: {0}")]
+    UnknownTenant(String),
+
+    /// The claim does not exist in the caller's tenant
+    #[error("Claim {0} not found")]
+    NotFound(String),
+
+    /// A claim with the same name already exists for a different user/agent/tenant
+    #[error("Claim name {0} is already used by another agent")]
+    NameCollision(String),
+
+    /// The claim did not report `Ready=True` before the deadline
+    #[error(
+        "Timed out after {}s waiting for {name} to be Ready: {}",
+        timeout.as_secs(),
+        describe(conditions)
+    )]
+    NotReady {
+        name: String,
+        timeout: Duration,
+        conditions: Vec<ClaimCondition>,
+    },
+
+    /// The claim was still present (held by finalizers) after the deadline
+    #[error("Timed out after {}s waiting for {name} to be deleted", timeout.as_secs())]
+    NotDeleted { name: String, timeout: Duration },
+
+    /// An update request did not change any field
+    #[error("No fields to update for {0}")]
+    EmptyUpdate(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn conditions() -> Vec<ClaimCondition> {
+        serde_json::from_value(json!([
+            {
+                "type": "Synced",
+                "status": "False",
+                "reason": "ReconcileError",
+                "message": "cannot compose resources: unknown field gpuType",
+                "lastTransitionTime": "2024-01-01T00:00:00Z"
+            },
+            { "type": "Ready", "status": "False", "reason": "Creating" }
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_condition_deserialization() {
+        let conditions = conditions();
+
+        assert_eq!(conditions.len(), 2);
+        assert_eq!(conditions[0].condition_type, "Synced");
+        assert_eq!(conditions[0].reason.as_deref(), Some("ReconcileError"));
+        assert!(!is_ready(&conditions));
+        assert!(!is_ready(&[]));
+    }
+
+    #[test]
+    fn test_is_ready() {
+        let conditions: Vec<ClaimCondition> =
+            serde_json::from_value(json!([{ "type": "Ready", "status": "True" }])).unwrap();
+        assert!(is_ready(&conditions));
+    }
+
+    #[test]
+    fn test_not_ready_error_includes_reason() {
+        let err = ClaimError::NotReady {
+            name: "mem-alice-bot".to_string(),
+            timeout: Duration::from_secs(300),
+            conditions: conditions(),
+        };
+
+        let message = err.to_string();
+        assert!(message.contains("300s"));
+        assert!(message.contains("Synced=False (ReconcileError: cannot compose resources"));
+        assert!(message.contains("Ready=False (Creating)"));
+    }
+}
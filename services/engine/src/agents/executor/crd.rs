@@ -0,0 +1,455 @@
+//! Claim Types
+//!
+//! Typed `AgentMemory` and `AgentWorker` resources matching the Crossplane
+//! XRDs in `infra/src/constructs/agent-xrds.ts`. Spec fields are validated on
+//! deserialization, so a malformed claim is rejected by the engine instead of
+//! surfacing as a Crossplane error minutes later.
+
+use kube::api::DynamicObject;
+use kube::core::NamespaceResourceScope;
+use kube::{CustomResource, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+use super::{ClaimCondition, ClaimError};
+
+/// Cloud provider backing a claim
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Gcp,
+    Aws,
+    Azure,
+}
+
+/// Database/storage engine for agent memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryType {
+    #[default]
+    Postgres,
+    Redis,
+    Qdrant,
+    Elasticsearch,
+}
+
+/// Performance tier for agent memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    #[default]
+    Small,
+    Medium,
+    Large,
+}
+
+/// GPU model for agent workers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum GpuType {
+    NvidiaTeslaT4,
+    NvidiaTeslaA100,
+    NvidiaL4,
+}
+
+/// Storage size in whole GiB.
+///
+/// Accepts any Kubernetes quantity (`10Gi`, `1Ti`, `2048Mi`) that is a whole
+/// number of GiB, and always serializes as `<n>Gi` because the compositions
+/// trim the `Gi` suffix to get the disk size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct StorageSize {
+    gib: u64,
+}
+
+const GIB: u128 = 1 << 30;
+
+impl StorageSize {
+    pub fn from_gib(gib: u64) -> Result<Self, ClaimError> {
+        if gib == 0 {
+            return Err(ClaimError::InvalidSpec("size must be greater than zero".to_string()));
+        }
+        Ok(Self { gib })
+    }
+
+    pub fn gib(&self) -> u64 {
+        self.gib
+    }
+}
+
+impl Default for StorageSize {
+    fn default() -> Self {
+        Self { gib: 10 }
+    }
+}
+
+impl FromStr for StorageSize {
+    type Err = ClaimError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ClaimError::InvalidSpec(format!("invalid size quantity: {:?}", s));
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, suffix) = s.split_at(split);
+        let number: u128 = number.parse().map_err(|_| invalid())?;
+
+        let multiplier: u128 = match suffix {
+            "Ki" => 1 << 10,
+            "Mi" => 1 << 20,
+            "Gi" => 1 << 30,
+            "Ti" => 1 << 40,
+            "Pi" => 1 << 50,
+            "" => 1,
+            "k" => 1_000,
+            "M" => 1_000_000,
+            "G" => 1_000_000_000,
+            "T" => 1_000_000_000_000,
+            "P" => 1_000_000_000_000_000,
+            _ => return Err(invalid()),
+        };
+
+        let bytes = number.checked_mul(multiplier).ok_or_else(invalid)?;
+        if bytes % GIB != 0 {
+            return Err(ClaimError::InvalidSpec(format!(
+                "size {:?} is not a whole number of Gi",
+                s
+            )));
+        }
+
+        let gib = u64::try_from(bytes / GIB).map_err(|_| invalid())?;
+        Self::from_gib(gib)
+    }
+}
+
+impl TryFrom<String> for StorageSize {
+    type Error = ClaimError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<StorageSize> for String {
+    fn from(size: StorageSize) -> Self {
+        size.to_string()
+    }
+}
+
+impl fmt::Display for StorageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}Gi", self.gib)
+    }
+}
+
+impl PartialSchema for StorageSize {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .description(Some(
+                "Storage size as a Kubernetes quantity that is a whole number of Gi (e.g. `10Gi`, `1Ti`)",
+            ))
+            .default(Some(serde_json::json!(StorageSize::default().to_string())))
+            .examples([serde_json::json!("20Gi")])
+            .into()
+    }
+}
+
+impl ToSchema for StorageSize {}
+
+/// Worker replica count, bounded to the XRD's `1..=10`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "i64", into = "i64")]
+pub struct Replicas(u8);
+
+impl Replicas {
+    pub const MIN: u8 = 1;
+    pub const MAX: u8 = 10;
+
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for Replicas {
+    fn default() -> Self {
+        Self(Self::MIN)
+    }
+}
+
+impl TryFrom<i64> for Replicas {
+    type Error = ClaimError;
+
+    fn try_from(n: i64) -> Result<Self, Self::Error> {
+        if (Self::MIN as i64..=Self::MAX as i64).contains(&n) {
+            Ok(Self(n as u8))
+        } else {
+            Err(ClaimError::InvalidSpec(format!(
+                "replicas must be between {} and {}, got {}",
+                Self::MIN,
+                Self::MAX,
+                n
+            )))
+        }
+    }
+}
+
+impl From<Replicas> for i64 {
+    fn from(replicas: Replicas) -> Self {
+        replicas.0 as i64
+    }
+}
+
+impl PartialSchema for Replicas {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::Integer)
+            .description(Some("Number of worker replicas"))
+            .minimum(Some(Replicas::MIN))
+            .maximum(Some(Replicas::MAX))
+            .default(Some(serde_json::json!(Replicas::MIN)))
+            .into()
+    }
+}
+
+impl ToSchema for Replicas {}
+
+/// Status reported by Crossplane on a claim
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClaimStatus {
+    #[serde(default)]
+    pub conditions: Vec<ClaimCondition>,
+}
+
+/// Spec of an `AgentMemory` claim (database/storage for an agent)
+#[derive(CustomResource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[kube(
+    group = "lornu.ai",
+    version = "v1alpha1",
+    kind = "AgentMemory",
+    plural = "agentmemories",
+    namespaced,
+    status = "ClaimStatus",
+    schema = "disabled"
+)]
+pub struct AgentMemorySpec {
+    pub provider: Provider,
+    #[serde(rename = "type")]
+    pub memory_type: MemoryType,
+    #[serde(default)]
+    pub size: StorageSize,
+    #[serde(default)]
+    pub tier: Tier,
+}
+
+/// Spec of an `AgentWorker` claim (compute for an agent)
+#[derive(CustomResource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[kube(
+    group = "lornu.ai",
+    version = "v1alpha1",
+    kind = "AgentWorker",
+    plural = "agentworkers",
+    namespaced,
+    status = "ClaimStatus",
+    schema = "disabled"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentWorkerSpec {
+    pub provider: Provider,
+    #[serde(default)]
+    pub gpu: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_type: Option<GpuType>,
+    #[serde(default)]
+    pub replicas: Replicas,
+    #[serde(default = "default_worker_timeout")]
+    pub timeout: String,
+}
+
+fn default_worker_timeout() -> String {
+    "30m".to_string()
+}
+
+/// Fields of an `AgentMemory` spec that can be changed after creation
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgentMemorySpecPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<StorageSize>,
+}
+
+/// Fields of an `AgentWorker` spec that can be changed after creation
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentWorkerSpecPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_type: Option<GpuType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<Replicas>,
+}
+
+/// A namespaced Crossplane claim managed by the executor.
+pub trait Claim:
+    Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+    + Clone
+    + fmt::Debug
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+    + 'static
+{
+    /// Prefix for generated claim names (`mem`, `worker`).
+    const NAME_PREFIX: &'static str;
+
+    /// Conditions currently reported by Crossplane.
+    fn conditions(&self) -> &[ClaimCondition];
+}
+
+/// Parse listed objects as claims of kind `K`, skipping (with a warning) any
+/// that no longer fit the typed spec, e.g. after a manual `kubectl edit`, so
+/// one bad claim does not hide the rest.
+pub fn parse_claims<K: Claim>(objects: Vec<DynamicObject>) -> Vec<K> {
+    objects
+        .into_iter()
+        .filter_map(|object| {
+            let name = object.name_any();
+            match serde_json::to_value(object).and_then(serde_json::from_value::<K>) {
+                Ok(claim) => Some(claim),
+                Err(e) => {
+                    tracing::warn!("Skipping {} {}: {}", K::kind(&()), name, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+impl Claim for AgentMemory {
+    const NAME_PREFIX: &'static str = "mem";
+
+    fn conditions(&self) -> &[ClaimCondition] {
+        self.status.as_ref().map(|s| s.conditions.as_slice()).unwrap_or_default()
+    }
+}
+
+impl Claim for AgentWorker {
+    const NAME_PREFIX: &'static str = "worker";
+
+    fn conditions(&self) -> &[ClaimCondition] {
+        self.status.as_ref().map(|s| s.conditions.as_slice()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_storage_size_parsing() {
+        assert_eq!("10Gi".parse::<StorageSize>().unwrap().gib(), 10);
+        assert_eq!("1Ti".parse::<StorageSize>().unwrap().gib(), 1024);
+        assert_eq!("2048Mi".parse::<StorageSize>().unwrap().gib(), 2);
+        assert_eq!("1Ti".parse::<StorageSize>().unwrap().to_string(), "1024Gi");
+
+        assert!("512Mi".parse::<StorageSize>().is_err());
+        assert!("10G".parse::<StorageSize>().is_err());
+        assert!("0Gi".parse::<StorageSize>().is_err());
+        assert!("small".parse::<StorageSize>().is_err());
+        assert!("10Gb".parse::<StorageSize>().is_err());
+    }
+
+    #[test]
+    fn test_replicas_bounds() {
+        assert!(Replicas::try_from(1).is_ok());
+        assert!(Replicas::try_from(10).is_ok());
+        assert!(Replicas::try_from(0).is_err());
+        assert!(Replicas::try_from(11).is_err());
+    }
+
+    #[test]
+    fn test_memory_claim_serialization() {
+        let claim = AgentMemory::new(
+            "mem-alice-bot",
+            AgentMemorySpec {
+                provider: Provider::Gcp,
+                memory_type: MemoryType::Postgres,
+                size: StorageSize::default(),
+                tier: Tier::Small,
+            },
+        );
+
+        let value = serde_json::to_value(&claim).unwrap();
+        assert_eq!(value["apiVersion"], "lornu.ai/v1alpha1");
+        assert_eq!(value["kind"], "AgentMemory");
+        assert_eq!(
+            value["spec"],
+            json!({ "provider": "gcp", "type": "postgres", "size": "10Gi", "tier": "small" })
+        );
+    }
+
+    #[test]
+    fn test_worker_spec_rejects_typos() {
+        let spec = json!({ "provider": "gcp", "gpu": true, "gpuType": "nvidia-l4", "replicas": 2 });
+        let spec: AgentWorkerSpec = serde_json::from_value(spec).unwrap();
+        assert_eq!(spec.gpu_type, Some(GpuType::NvidiaL4));
+        assert_eq!(spec.timeout, "30m");
+
+        let typo = json!({ "provider": "gpc" });
+        assert!(serde_json::from_value::<AgentWorkerSpec>(typo).is_err());
+
+        let too_many = json!({ "provider": "gcp", "replicas": 50 });
+        assert!(serde_json::from_value::<AgentWorkerSpec>(too_many).is_err());
+    }
+
+    #[test]
+    fn test_claim_conditions_from_status() {
+        let claim: AgentWorker = serde_json::from_value(json!({
+            "apiVersion": "lornu.ai/v1alpha1",
+            "kind": "AgentWorker",
+            "metadata": { "name": "worker-alice-bot" },
+            "spec": { "provider": "aws" },
+            "status": { "conditions": [{ "type": "Ready", "status": "True" }] }
+        }))
+        .unwrap();
+
+        assert_eq!(claim.conditions().len(), 1);
+        assert!(claim.conditions()[0].is_true());
+    }
+
+    #[test]
+    fn test_parse_claims_skips_invalid_items() {
+        let object = |name: &str, size: &str| -> DynamicObject {
+            serde_json::from_value(json!({
+                "metadata": { "name": name },
+                "spec": { "provider": "gcp", "type": "redis", "size": size, "tier": "small" }
+            }))
+            .unwrap()
+        };
+
+        let objects = vec![object("mem-a", "10Gi"), object("mem-b", "512Mi")];
+        let claims = parse_claims::<AgentMemory>(objects);
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].name_any(), "mem-a");
+    }
+
+    #[test]
+    fn test_spec_patch_only_includes_changed_fields() {
+        let patch = AgentWorkerSpecPatch {
+            replicas: Some(Replicas::try_from(3).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(&patch).unwrap(), json!({ "replicas": 3 }));
+
+        let patch = AgentMemorySpecPatch::default();
+        assert_eq!(serde_json::to_value(&patch).unwrap(), json!({}));
+    }
+}
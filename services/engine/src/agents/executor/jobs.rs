@@ -0,0 +1,194 @@
+//! Provisioning Jobs
+//!
+//! Tracks claims that are waiting to become Ready in the background, so the
+//! API can return immediately and report progress through a job id.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::ClaimCondition;
+
+/// How long finished jobs are kept before being pruned.
+const JOB_RETENTION_HOURS: i64 = 24;
+
+/// Lifecycle phase of a provisioning job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPhase {
+    /// Claim created, no conditions reported yet
+    Pending,
+    /// Crossplane is reconciling the claim
+    Provisioning,
+    /// Claim reported `Ready=True`
+    Ready,
+    /// Claim did not become Ready (timeout or watch failure)
+    Failed,
+}
+
+impl JobPhase {
+    /// Whether the job has reached a final outcome.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobPhase::Ready | JobPhase::Failed)
+    }
+}
+
+/// A claim being provisioned in the background
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProvisionJob {
+    /// Job identifier returned to the caller
+    pub id: Uuid,
+    /// Tenant that requested the claim
+    pub tenant: String,
+    /// Claim kind (`AgentMemory`, `AgentWorker`)
+    pub kind: String,
+    /// Name of the claim being provisioned
+    pub claim_name: String,
+    /// Current phase
+    pub phase: JobPhase,
+    /// Latest conditions reported by Crossplane
+    pub conditions: Vec<ClaimCondition>,
+    /// Failure reason, if the job failed
+    pub error: Option<String>,
+    /// When the job was created
+    pub created_at: DateTime<Utc>,
+    /// When the job last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// In-memory registry of provisioning jobs.
+///
+/// Cheap to clone; all clones share the same registry. Locks are never held
+/// across an `.await`, so a plain `RwLock` is sufficient.
+#[derive(Clone, Default)]
+pub struct ProvisionJobs {
+    jobs: Arc<RwLock<HashMap<Uuid, ProvisionJob>>>,
+}
+
+impl ProvisionJobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new job for a claim that has just been created.
+    pub fn create(&self, tenant: &str, kind: &str, claim_name: &str) -> ProvisionJob {
+        let now = Utc::now();
+        let job = ProvisionJob {
+            id: Uuid::new_v4(),
+            tenant: tenant.to_string(),
+            kind: kind.to_string(),
+            claim_name: claim_name.to_string(),
+            phase: JobPhase::Pending,
+            conditions: Vec::new(),
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut jobs = self.jobs.write().unwrap_or_else(|e| e.into_inner());
+        let cutoff = now - Duration::hours(JOB_RETENTION_HOURS);
+        jobs.retain(|_, j| !j.phase.is_finished() || j.updated_at > cutoff);
+        jobs.insert(job.id, job.clone());
+
+        job
+    }
+
+    /// Look up a tenant's job by id.
+    pub fn get(&self, tenant: &str, id: &Uuid) -> Option<ProvisionJob> {
+        let jobs = self.jobs.read().unwrap_or_else(|e| e.into_inner());
+        jobs.get(id).filter(|job| job.tenant == tenant).cloned()
+    }
+
+    /// Record the latest conditions reported for a job's claim.
+    pub fn update_conditions(&self, id: &Uuid, conditions: &[ClaimCondition]) {
+        self.update(id, |job| {
+            job.phase = JobPhase::Provisioning;
+            job.conditions = conditions.to_vec();
+        });
+    }
+
+    /// Record the final outcome of a job.
+    pub fn finish(&self, id: &Uuid, outcome: anyhow::Result<Vec<ClaimCondition>>) {
+        self.update(id, |job| match outcome {
+            Ok(conditions) => {
+                job.phase = JobPhase::Ready;
+                job.conditions = conditions;
+            }
+            Err(e) => {
+                job.phase = JobPhase::Failed;
+                job.error = Some(e.to_string());
+            }
+        });
+    }
+
+    fn update(&self, id: &Uuid, f: impl FnOnce(&mut ProvisionJob)) {
+        let mut jobs = self.jobs.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.get_mut(id) {
+            f(job);
+            job.updated_at = Utc::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(condition_type: &str, status: &str) -> ClaimCondition {
+        ClaimCondition {
+            condition_type: condition_type.to_string(),
+            status: status.to_string(),
+            reason: None,
+            message: None,
+            last_transition_time: None,
+        }
+    }
+
+    #[test]
+    fn test_job_lifecycle() {
+        let jobs = ProvisionJobs::new();
+        let job = jobs.create("search", "AgentMemory", "mem-alice-bot");
+        assert_eq!(job.phase, JobPhase::Pending);
+
+        jobs.update_conditions(&job.id, &[condition("Synced", "False")]);
+        let job = jobs.get("search", &job.id).unwrap();
+        assert_eq!(job.phase, JobPhase::Provisioning);
+        assert_eq!(job.conditions.len(), 1);
+
+        jobs.finish(&job.id, Ok(vec![condition("Ready", "True")]));
+        let job = jobs.get("search", &job.id).unwrap();
+        assert_eq!(job.phase, JobPhase::Ready);
+        assert!(job.phase.is_finished());
+    }
+
+    #[test]
+    fn test_job_failure_keeps_conditions() {
+        let jobs = ProvisionJobs::new();
+        let job = jobs.create("search", "AgentWorker", "worker-alice-bot");
+
+        jobs.update_conditions(&job.id, &[condition("Synced", "False")]);
+        jobs.finish(&job.id, Err(anyhow::anyhow!("timed out")));
+
+        let job = jobs.get("search", &job.id).unwrap();
+        assert_eq!(job.phase, JobPhase::Failed);
+        assert_eq!(job.error.as_deref(), Some("timed out"));
+        assert_eq!(job.conditions[0].condition_type, "Synced");
+    }
+
+    #[test]
+    fn test_unknown_job() {
+        assert!(ProvisionJobs::new().get("search", &Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_job_hidden_from_other_tenants() {
+        let jobs = ProvisionJobs::new();
+        let job = jobs.create("search", "AgentMemory", "mem-alice-bot");
+
+        assert!(jobs.get("search", &job.id).is_some());
+        assert!(jobs.get("infra", &job.id).is_none());
+    }
+}
@@ -0,0 +1,370 @@
+//! Crossplane Executor
+//!
+//! Creates Crossplane Claims at runtime via the Kubernetes API.
+//! This allows agents to dynamically provision infrastructure without YAML.
+
+mod conditions;
+mod crd;
+mod jobs;
+mod tenancy;
+
+use anyhow::{Context, Result};
+use futures::{pin_mut, TryStreamExt};
+use kube::{
+    api::{
+        Api, ApiResource, DeleteParams, DynamicObject, ListParams, Patch, PatchParams, PostParams,
+    },
+    runtime::{
+        conditions::is_deleted,
+        wait::await_condition,
+        watcher, WatchStreamExt,
+    },
+    Client, ResourceExt,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+pub use conditions::{ClaimCondition, ClaimError};
+pub use crd::{
+    AgentMemory, AgentMemorySpec, AgentMemorySpecPatch, AgentWorker, AgentWorkerSpec,
+    AgentWorkerSpecPatch, Claim, GpuType, MemoryType, Provider, Replicas,
+    StorageSize, Tier,
+};
+pub use jobs::{JobPhase, ProvisionJob, ProvisionJobs};
+pub use tenancy::{claim_name, Tenant, TenantConfig};
+
+const LABEL_USER: &str = "lornu.ai/user";
+const LABEL_AGENT: &str = "lornu.ai/agent";
+const LABEL_TENANT: &str = "lornu.ai/tenant";
+const LABEL_MANAGED_BY: &str = "lornu.ai/managed-by";
+
+/// Field manager used for server-side apply updates.
+const FIELD_MANAGER: &str = "lornu-engine";
+
+/// Default deadline for a claim to report `Ready=True`.
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Executor for creating Crossplane resources at runtime.
+pub struct CrossplaneExecutor {
+    client: Client,
+    ready_timeout: Duration,
+}
+
+impl CrossplaneExecutor {
+    /// Create a new executor with in-cluster credentials.
+    pub async fn new() -> Result<Self> {
+        let client = Client::try_default()
+            .await
+            .context("Failed to create Kubernetes client")?;
+
+        info!("CrossplaneExecutor initialized");
+        Ok(Self {
+            client,
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+        })
+    }
+
+    /// Set how long to wait for a claim to become Ready before giving up.
+    pub fn with_ready_timeout(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    fn api<K: Claim>(&self, tenant: &Tenant) -> Api<K> {
+        Api::namespaced(self.client.clone(), &tenant.namespace)
+    }
+
+    /// Provision an AgentMemory claim for an agent and wait for it to become Ready.
+    pub async fn provision_agent_memory(
+        &self,
+        tenant: &Tenant,
+        user: &str,
+        agent: &str,
+        spec: AgentMemorySpec,
+    ) -> Result<String> {
+        let name = self.create_agent_memory_claim(tenant, user, agent, spec).await?;
+        self.wait_for_ready::<AgentMemory>(tenant, &name, |_| {}).await?;
+
+        Ok(name)
+    }
+
+    /// Create an AgentMemory claim without waiting for it to become Ready.
+    pub async fn create_agent_memory_claim(
+        &self,
+        tenant: &Tenant,
+        user: &str,
+        agent: &str,
+        spec: AgentMemorySpec,
+    ) -> Result<String> {
+        let name = claim_name(AgentMemory::NAME_PREFIX, user, agent)?;
+        info!("Provisioning AgentMemory: {} (tenant: {})", name, tenant.name);
+
+        let mut claim = AgentMemory::new(&name, spec);
+        claim.metadata.labels = Some(claim_labels(tenant, user, agent));
+
+        self.create_claim(tenant, claim).await?;
+
+        Ok(name)
+    }
+
+    /// Provision an AgentWorker claim for compute and wait for it to become Ready.
+    pub async fn provision_agent_worker(
+        &self,
+        tenant: &Tenant,
+        user: &str,
+        agent: &str,
+        spec: AgentWorkerSpec,
+    ) -> Result<String> {
+        let name = self.create_agent_worker_claim(tenant, user, agent, spec).await?;
+        self.wait_for_ready::<AgentWorker>(tenant, &name, |_| {}).await?;
+
+        Ok(name)
+    }
+
+    /// Create an AgentWorker claim without waiting for it to become Ready.
+    pub async fn create_agent_worker_claim(
+        &self,
+        tenant: &Tenant,
+        user: &str,
+        agent: &str,
+        spec: AgentWorkerSpec,
+    ) -> Result<String> {
+        let name = claim_name(AgentWorker::NAME_PREFIX, user, agent)?;
+        info!("Provisioning AgentWorker: {} (tenant: {})", name, tenant.name);
+
+        let mut claim = AgentWorker::new(&name, spec);
+        claim.metadata.labels = Some(claim_labels(tenant, user, agent));
+
+        self.create_claim(tenant, claim).await?;
+
+        Ok(name)
+    }
+
+    /// Create a Crossplane claim, skipping it if the same agent already has one.
+    ///
+    /// A claim with the same name but different user/agent/tenant labels (e.g.
+    /// `alice-x` + `bot` vs `alice` + `x-bot`) is reported as a collision.
+    async fn create_claim<K: Claim>(&self, tenant: &Tenant, claim: K) -> Result<()> {
+        let api = self.api::<K>(tenant);
+        let name = claim.name_any();
+
+        if let Some(existing) = api.get_opt(&name).await? {
+            let owned = [LABEL_USER, LABEL_AGENT, LABEL_TENANT]
+                .iter()
+                .all(|label| existing.labels().get(*label) == claim.labels().get(*label));
+            if !owned {
+                return Err(ClaimError::NameCollision(name).into());
+            }
+
+            info!("Claim {} already exists, skipping creation", name);
+            return Ok(());
+        }
+
+        api.create(&PostParams::default(), &claim).await?;
+
+        info!("Created claim: {}", name);
+        Ok(())
+    }
+
+    /// Wait for a claim to become ready.
+    ///
+    /// Watches the claim rather than polling it, so readiness is observed as soon
+    /// as Crossplane reports it. Conditions that are not `True` (e.g. `Synced=False`
+    /// with a `ReconcileError`) are logged and passed to `on_progress` as they
+    /// change, and returned in [`ClaimError::NotReady`] if the deadline passes.
+    pub async fn wait_for_ready<K: Claim>(
+        &self,
+        tenant: &Tenant,
+        name: &str,
+        mut on_progress: impl FnMut(&[ClaimCondition]),
+    ) -> Result<Vec<ClaimCondition>> {
+        let api = self.api::<K>(tenant);
+
+        let mut last_seen: Vec<ClaimCondition> = Vec::new();
+
+        let watch = async {
+            let stream = watcher::watch_object(api, name).default_backoff();
+            pin_mut!(stream);
+
+            while let Some(obj) = stream.try_next().await? {
+                let Some(obj) = obj else {
+                    info!("Claim {} not found yet, waiting", name);
+                    continue;
+                };
+
+                let current = obj.conditions();
+
+                if conditions::is_ready(current) {
+                    info!("Claim {} is Ready", name);
+                    return Ok(current.to_vec());
+                }
+
+                if current != last_seen.as_slice() {
+                    for condition in current.iter().filter(|c| !c.is_true()) {
+                        warn!("Claim {} not ready: {}", name, condition);
+                    }
+                    on_progress(current);
+                    last_seen = current.to_vec();
+                }
+            }
+
+            anyhow::bail!("Watch on {} ended before it became Ready", name);
+        };
+
+        match timeout(self.ready_timeout, watch).await {
+            Ok(result) => result,
+            Err(_) => Err(ClaimError::NotReady {
+                name: name.to_string(),
+                timeout: self.ready_timeout,
+                conditions: last_seen,
+            }
+            .into()),
+        }
+    }
+
+    /// List the agent resources belonging to a tenant.
+    pub async fn list_agent_resources(&self, tenant: &Tenant) -> Result<Vec<serde_json::Value>> {
+        let mut resources = Vec::new();
+        let params = ListParams::default().labels(&format!(
+            "{}={},{}=engine",
+            LABEL_TENANT, tenant.name, LABEL_MANAGED_BY
+        ));
+
+        let memories = self.list_claims::<AgentMemory>(tenant, &params).await?;
+        resources.extend(memories.iter().map(resource_summary));
+        let workers = self.list_claims::<AgentWorker>(tenant, &params).await?;
+        resources.extend(workers.iter().map(resource_summary));
+
+        Ok(resources)
+    }
+
+    /// List claims of kind `K` untyped, so one that fails to deserialize is
+    /// skipped instead of failing the whole list.
+    async fn list_claims<K: Claim>(&self, tenant: &Tenant, params: &ListParams) -> Result<Vec<K>> {
+        let resource = ApiResource::erase::<K>(&());
+        let api: Api<DynamicObject> =
+            Api::namespaced_with(self.client.clone(), &tenant.namespace, &resource);
+        Ok(crd::parse_claims(api.list(params).await?.items))
+    }
+
+    /// Update the size of an existing AgentMemory claim.
+    pub async fn update_agent_memory(
+        &self,
+        tenant: &Tenant,
+        name: &str,
+        patch: AgentMemorySpecPatch,
+    ) -> Result<()> {
+        if patch.size.is_none() {
+            return Err(ClaimError::EmptyUpdate(name.to_string()).into());
+        }
+        self.apply_claim_patch::<AgentMemory>(tenant, name, &patch).await
+    }
+
+    /// Rescale or change the GPU settings of an existing AgentWorker claim.
+    pub async fn update_agent_worker(
+        &self,
+        tenant: &Tenant,
+        name: &str,
+        patch: AgentWorkerSpecPatch,
+    ) -> Result<()> {
+        if patch.gpu.is_none() && patch.gpu_type.is_none() && patch.replicas.is_none() {
+            return Err(ClaimError::EmptyUpdate(name.to_string()).into());
+        }
+        self.apply_claim_patch::<AgentWorker>(tenant, name, &patch).await
+    }
+
+    /// Apply a partial spec to an existing claim with server-side apply.
+    ///
+    /// The claim must already exist: server-side apply would otherwise create it.
+    async fn apply_claim_patch<K: Claim>(
+        &self,
+        tenant: &Tenant,
+        name: &str,
+        spec: &impl Serialize,
+    ) -> Result<()> {
+        let api = self.api::<K>(tenant);
+
+        self.get_owned_claim(&api, tenant, name).await?;
+
+        let patch = json!({
+            "apiVersion": K::api_version(&()),
+            "kind": K::kind(&()),
+            "metadata": { "name": name },
+            "spec": spec
+        });
+        api.patch(name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&patch))
+            .await?;
+
+        info!("Updated claim: {}", name);
+        Ok(())
+    }
+
+    /// Fetch a claim, treating claims of other tenants (or not managed by the
+    /// engine) as missing so their existence is not revealed.
+    async fn get_owned_claim<K: Claim>(&self, api: &Api<K>, tenant: &Tenant, name: &str) -> Result<K> {
+        let claim = api
+            .get_opt(name)
+            .await?
+            .filter(|claim| {
+                claim.labels().get(LABEL_TENANT) == Some(&tenant.name)
+                    && claim.labels().get(LABEL_MANAGED_BY).map(String::as_str) == Some("engine")
+            })
+            .ok_or_else(|| ClaimError::NotFound(name.to_string()))?;
+
+        Ok(claim)
+    }
+
+    /// Delete an agent resource claim.
+    ///
+    /// With `wait_for_finalizers`, returns only once the claim is gone, i.e. after
+    /// Crossplane has removed its finalizers and the composed resources.
+    pub async fn delete_claim<K: Claim>(
+        &self,
+        tenant: &Tenant,
+        name: &str,
+        wait_for_finalizers: bool,
+    ) -> Result<()> {
+        let api = self.api::<K>(tenant);
+
+        self.get_owned_claim(&api, tenant, name).await?;
+
+        let pending = api.delete(name, &DeleteParams::default()).await?;
+        info!("Deleted claim: {}", name);
+
+        if let (true, Some(uid)) = (wait_for_finalizers, pending.left().and_then(|obj| obj.uid())) {
+            info!("Waiting for finalizers on {} to clear", name);
+            timeout(self.ready_timeout, await_condition(api, name, is_deleted(&uid)))
+                .await
+                .map_err(|_| ClaimError::NotDeleted {
+                    name: name.to_string(),
+                    timeout: self.ready_timeout,
+                })??;
+            info!("Claim {} is gone", name);
+        }
+
+        Ok(())
+    }
+}
+
+/// Labels applied to every claim created by the engine.
+fn claim_labels(tenant: &Tenant, user: &str, agent: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (LABEL_USER.to_string(), user.to_string()),
+        (LABEL_AGENT.to_string(), agent.to_string()),
+        (LABEL_TENANT.to_string(), tenant.name.clone()),
+        (LABEL_MANAGED_BY.to_string(), "engine".to_string()),
+    ])
+}
+
+/// Summary of a claim for the agent status endpoint.
+fn resource_summary<K: Claim>(claim: &K) -> serde_json::Value {
+    json!({
+        "kind": K::kind(&()),
+        "name": claim.name_any(),
+        "status": { "conditions": claim.conditions() }
+    })
+}
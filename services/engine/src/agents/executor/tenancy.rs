@@ -0,0 +1,217 @@
+//! Tenant Isolation
+//!
+//! Maps tenants to Kubernetes namespaces and derives RFC 1123-compliant claim
+//! names, so one team can never see or touch another team's claims.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use super::ClaimError;
+
+/// Namespace used when no tenant mapping is configured.
+pub const DEFAULT_NAMESPACE: &str = "lornu-ai";
+
+/// Tenant used when no tenant mapping is configured.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Maximum length of a claim name.
+///
+/// Crossplane copies the claim name into `crossplane.io/claim-name` labels,
+/// and label values are limited to 63 characters.
+pub const MAX_CLAIM_NAME_LEN: usize = 63;
+
+/// Number of hex characters of the name hash appended to shortened names.
+const HASH_LEN: usize = 8;
+
+/// A tenant and the namespace its claims live in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant {
+    pub name: String,
+    pub namespace: String,
+}
+
+/// Tenant-to-namespace mapping
+#[derive(Debug, Clone)]
+pub struct TenantConfig {
+    namespaces: HashMap<String, String>,
+    default_tenant: Option<String>,
+}
+
+impl Default for TenantConfig {
+    /// A single `default` tenant in the `lornu-ai` namespace.
+    fn default() -> Self {
+        Self {
+            namespaces: HashMap::from([(DEFAULT_TENANT.to_string(), DEFAULT_NAMESPACE.to_string())]),
+            default_tenant: Some(DEFAULT_TENANT.to_string()),
+        }
+    }
+}
+
+impl TenantConfig {
+    /// Load the mapping from the environment.
+    ///
+    /// - `LORNU_TENANT_NAMESPACES`: comma-separated `tenant=namespace` pairs
+    ///   (e.g. `search=lornu-search,infra=lornu-infra`)
+    /// - `LORNU_DEFAULT_TENANT`: tenant for requests that do not name one
+    ///
+    /// Without `LORNU_TENANT_NAMESPACES`, falls back to [`TenantConfig::default`].
+    pub fn from_env() -> Result<Self, ClaimError> {
+        match std::env::var("LORNU_TENANT_NAMESPACES") {
+            Ok(spec) => Self::parse(&spec, std::env::var("LORNU_DEFAULT_TENANT").ok()),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Parse a `tenant=namespace,...` mapping.
+    pub fn parse(spec: &str, default_tenant: Option<String>) -> Result<Self, ClaimError> {
+        let mut namespaces = HashMap::new();
+
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (tenant, namespace) = pair
+                .split_once('=')
+                .ok_or_else(|| ClaimError::InvalidName(format!("invalid tenant mapping {:?}", pair)))?;
+            let (tenant, namespace) = (tenant.trim(), namespace.trim());
+
+            validate_label("tenant", tenant)?;
+            validate_label("namespace", namespace)?;
+            namespaces.insert(tenant.to_string(), namespace.to_string());
+        }
+
+        if let Some(default) = &default_tenant {
+            if !namespaces.contains_key(default) {
+                return Err(ClaimError::UnknownTenant(default.clone()));
+            }
+        }
+
+        Ok(Self {
+            namespaces,
+            default_tenant,
+        })
+    }
+
+    /// Resolve the caller's tenant, falling back to the default tenant if one is configured.
+    pub fn resolve(&self, tenant: Option<&str>) -> Result<Tenant, ClaimError> {
+        let name = tenant
+            .or(self.default_tenant.as_deref())
+            .ok_or(ClaimError::MissingTenant)?;
+
+        let namespace = self
+            .namespaces
+            .get(name)
+            .ok_or_else(|| ClaimError::UnknownTenant(name.to_string()))?;
+
+        Ok(Tenant {
+            name: name.to_string(),
+            namespace: namespace.clone(),
+        })
+    }
+}
+
+/// Validate a value as an RFC 1123 label: at most 63 lowercase alphanumerics
+/// or `-`, starting and ending with an alphanumeric.
+pub fn validate_label(field: &str, value: &str) -> Result<(), ClaimError> {
+    let valid = !value.is_empty()
+        && value.len() <= 63
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !value.starts_with('-')
+        && !value.ends_with('-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ClaimError::InvalidName(format!(
+            "{} {:?} must be a lowercase RFC 1123 label (a-z, 0-9, '-', at most 63 characters)",
+            field, value
+        )))
+    }
+}
+
+/// Build the claim name for a user's agent.
+///
+/// Names longer than [`MAX_CLAIM_NAME_LEN`] are truncated and suffixed with a
+/// hash of the full name, so the result is deterministic and distinct for
+/// different inputs sharing a long prefix.
+pub fn claim_name(prefix: &str, user: &str, agent: &str) -> Result<String, ClaimError> {
+    validate_label("user", user)?;
+    validate_label("agent", agent)?;
+
+    let name = format!("{}-{}-{}", prefix, user, agent);
+    if name.len() <= MAX_CLAIM_NAME_LEN {
+        return Ok(name);
+    }
+
+    let digest = Sha256::digest(name.as_bytes());
+    let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let keep = MAX_CLAIM_NAME_LEN - HASH_LEN - 1;
+    let truncated = name[..keep].trim_end_matches('-');
+
+    Ok(format!("{}-{}", truncated, &hash[..HASH_LEN]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_label() {
+        assert!(validate_label("user", "alice").is_ok());
+        assert!(validate_label("user", "team-7").is_ok());
+
+        assert!(validate_label("user", "").is_err());
+        assert!(validate_label("user", "Alice").is_err());
+        assert!(validate_label("user", "alice_bob").is_err());
+        assert!(validate_label("user", "-alice").is_err());
+        assert!(validate_label("user", "alice-").is_err());
+        assert!(validate_label("user", &"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_claim_name_short() {
+        assert_eq!(claim_name("mem", "alice", "bot").unwrap(), "mem-alice-bot");
+    }
+
+    #[test]
+    fn test_claim_name_long_is_hashed_deterministically() {
+        let user = "a".repeat(40);
+        let first = claim_name("worker", &user, "summarizer-agent").unwrap();
+        let second = claim_name("worker", &user, "summarizer-agent").unwrap();
+        let other = claim_name("worker", &user, "summarizer-agent2").unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert!(first.len() <= MAX_CLAIM_NAME_LEN);
+        assert!(validate_label("claim", &first).is_ok());
+    }
+
+    #[test]
+    fn test_claim_name_rejects_invalid_parts() {
+        assert!(claim_name("mem", "Alice", "bot").is_err());
+        assert!(claim_name("mem", "alice", "my bot").is_err());
+    }
+
+    #[test]
+    fn test_tenant_config_default() {
+        let tenant = TenantConfig::default().resolve(None).unwrap();
+        assert_eq!(tenant.name, DEFAULT_TENANT);
+        assert_eq!(tenant.namespace, DEFAULT_NAMESPACE);
+    }
+
+    #[test]
+    fn test_tenant_config_parse() {
+        let config = TenantConfig::parse("search=lornu-search, infra=lornu-infra", None).unwrap();
+
+        assert_eq!(config.resolve(Some("infra")).unwrap().namespace, "lornu-infra");
+        assert!(matches!(config.resolve(Some("other")), Err(ClaimError::UnknownTenant(_))));
+        assert!(matches!(config.resolve(None), Err(ClaimError::MissingTenant)));
+    }
+
+    #[test]
+    fn test_tenant_config_parse_errors() {
+        assert!(TenantConfig::parse("search", None).is_err());
+        assert!(TenantConfig::parse("search=Lornu_Search", None).is_err());
+        assert!(TenantConfig::parse("search=lornu-search", Some("infra".to_string())).is_err());
+    }
+}
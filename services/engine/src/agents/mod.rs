@@ -4,7 +4,7 @@
 //! - `cherry_pick`: Context-aware cherry-pick with learning from past conflicts
 //! - `cyber`: Security agents (Zero Trust IAM hardening)
 //! - `dns_sync`: Multi-cloud DNS orchestration (Issue #118)
-//! - `executor`: Task execution and orchestration
+//! - `executor`: Crossplane claim provisioning and readiness tracking
 //! - `lifecycle`: Secret lifecycle management and cleanup
 //! - `service_discovery`: Multi-cloud service discovery with federated identity (Issue #119)
 //! - `ssh_key`: SSH key generation and GCP Secret Manager storage (Issue #176)
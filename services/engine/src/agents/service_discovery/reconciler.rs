@@ -5,7 +5,7 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing::{info, warn, error};
 
 use super::discovery::MultiCloudDiscovery;
@@ -216,6 +216,7 @@ impl CrossCloudReconciler {
                 let start = Instant::now();
                 let cert = gcp.check_managed_certificate(domain, &creds).await;
                 let latency = start.elapsed().as_millis() as u64;
+                record_probe_latency("gcp", start.elapsed());
 
                 provider_latencies.push(("gcp", latency, cert.is_ok() && !cert.unwrap().is_failed));
             }
@@ -227,6 +228,7 @@ impl CrossCloudReconciler {
                 let start = Instant::now();
                 let health = azure.check_traffic_manager(&creds).await;
                 let latency = start.elapsed().as_millis() as u64;
+                record_probe_latency("azure", start.elapsed());
 
                 provider_latencies.push(("azure", latency, health.is_ok() && health.unwrap().healthy));
             }
@@ -306,6 +308,12 @@ impl CrossCloudReconciler {
     }
 }
 
+/// Record how long a provider probe took, so latency can be graphed and alerted on.
+fn record_probe_latency(provider: &'static str, elapsed: Duration) {
+    metrics::histogram!("reconciler_probe_duration_seconds", "provider" => provider)
+        .record(elapsed.as_secs_f64());
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
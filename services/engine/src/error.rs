@@ -0,0 +1,510 @@
+//! API Errors
+//!
+//! Structured error responses for the engine HTTP API. Every failure maps to a
+//! proper HTTP status and carries a stable machine-readable `code` plus the
+//! request's correlation id, so callers can branch and retry without matching
+//! on message text.
+
+use axum::{
+    async_trait,
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts, Path, Query, Request,
+    },
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{error, warn};
+use utoipa::ToSchema;
+
+use crate::agents::executor::ClaimError;
+use crate::tools::cloudflare::CloudflareApiError;
+
+/// Header carrying the correlation id, accepted from callers and echoed back.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-supplied request id that is accepted as-is.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// Correlation id of the request being handled.
+    static REQUEST_ID: String;
+}
+
+/// Correlation id of the current request, if called while handling one.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Always `error`
+    #[schema(example = "error")]
+    pub status: String,
+    /// Stable machine-readable error code
+    #[schema(example = "not_found")]
+    pub code: String,
+    /// Human-readable description
+    pub message: String,
+    /// Whether the same request may succeed if retried later
+    pub retryable: bool,
+    /// Correlation id, also returned in the `X-Request-Id` header
+    pub request_id: Option<String>,
+}
+
+/// Errors returned by engine API handlers
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The request was malformed or failed validation (400)
+    #[error("{0}")]
+    InvalidRequest(String),
+
+    /// No valid credentials were presented (401)
+    #[error("{0}")]
+    Unauthorized(String),
+
+    /// The caller may not perform this operation (403)
+    #[error("{0}")]
+    Forbidden(String),
+
+    /// The resource does not exist for this caller (404)
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The request conflicts with an existing resource (409)
+    #[error("{0}")]
+    Conflict(String),
+
+    /// An optional integration is not configured on this engine (503)
+    #[error("{0} is not configured")]
+    NotConfigured(&'static str),
+
+    /// A dependency (Kubernetes, Cloudflare, ...) could not be reached (503)
+    #[error("{0}")]
+    Unavailable(String),
+
+    /// A dependency answered with an error of its own (502)
+    #[error("{0}")]
+    BadGateway(String),
+
+    /// An operation did not finish before its deadline (504)
+    #[error("{0}")]
+    Timeout(String),
+
+    /// Anything else (500)
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::NotConfigured(_) | ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::NotConfigured(_) => "not_configured",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::BadGateway(_) => "bad_gateway",
+            ApiError::Timeout(_) => "timeout",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether the same request may succeed if retried later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ApiError::Unavailable(_) | ApiError::BadGateway(_) | ApiError::Timeout(_))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = current_request_id();
+
+        if status.is_server_error() {
+            error!("[{}] {} ({})", request_id.as_deref().unwrap_or("-"), self, self.code());
+        } else {
+            warn!("[{}] {} ({})", request_id.as_deref().unwrap_or("-"), self, self.code());
+        }
+
+        let body = Json(ErrorBody {
+            status: "error".to_string(),
+            code: self.code().to_string(),
+            message: self.to_string(),
+            retryable: self.is_retryable(),
+            request_id,
+        });
+
+        let mut response = (status, body).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+impl From<ClaimError> for ApiError {
+    fn from(e: ClaimError) -> Self {
+        let message = e.to_string();
+        match e {
+            ClaimError::InvalidSpec(_) | ClaimError::InvalidName(_) | ClaimError::EmptyUpdate(_) => {
+                ApiError::InvalidRequest(message)
+            }
+            ClaimError::MissingTenant | ClaimError::UnknownTenant(_) => ApiError::Forbidden(message),
+            ClaimError::NotFound(_) => ApiError::NotFound(message),
+            ClaimError::NameCollision(_) => ApiError::Conflict(message),
+            ClaimError::NotReady { .. } | ClaimError::NotDeleted { .. } => ApiError::Timeout(message),
+        }
+    }
+}
+
+impl From<kube::Error> for ApiError {
+    fn from(e: kube::Error) -> Self {
+        match &e {
+            kube::Error::Api(response) => match response.code {
+                400 | 422 => ApiError::InvalidRequest(response.message.clone()),
+                403 => ApiError::Forbidden(response.message.clone()),
+                404 => ApiError::NotFound(response.message.clone()),
+                409 => ApiError::Conflict(response.message.clone()),
+                429 | 503 => ApiError::Unavailable(response.message.clone()),
+                504 => ApiError::Timeout(response.message.clone()),
+                _ => ApiError::Internal(e.to_string()),
+            },
+            kube::Error::HyperError(_) | kube::Error::Service(_) => {
+                ApiError::Unavailable(format!("Kubernetes API unavailable: {}", e))
+            }
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<CloudflareApiError> for ApiError {
+    fn from(e: CloudflareApiError) -> Self {
+        let message = e.to_string();
+        match e {
+            CloudflareApiError::Invalid(_) => ApiError::InvalidRequest(message),
+            CloudflareApiError::Conflict(_) => ApiError::Conflict(message),
+            CloudflareApiError::Upstream { .. } => ApiError::BadGateway(message),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    /// Recover the typed cause of an executor or tool error.
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<ClaimError>() {
+            Ok(claim) => return claim.into(),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<kube::Error>() {
+            Ok(kube) => return kube.into(),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<CloudflareApiError>() {
+            Ok(cloudflare) => return cloudflare.into(),
+            Err(e) => e,
+        };
+        if let Some(http) = e.downcast_ref::<reqwest::Error>() {
+            if http.is_timeout() {
+                return ApiError::Timeout(e.to_string());
+            }
+            if http.is_connect() {
+                return ApiError::Unavailable(e.to_string());
+            }
+        }
+        ApiError::Internal(format!("{:#}", e))
+    }
+}
+
+/// An extractor rejection: invalid input, unless axum reports its own fault.
+fn rejection(status: StatusCode, message: String) -> ApiError {
+    if status.is_server_error() {
+        ApiError::Internal(message)
+    } else {
+        ApiError::InvalidRequest(message)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        rejection(e.status(), e.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(e: PathRejection) -> Self {
+        rejection(e.status(), e.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> Self {
+        rejection(e.status(), e.body_text())
+    }
+}
+
+/// [`axum::Json`] whose rejection is an [`ApiError`]
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// [`axum::extract::Path`] whose rejection is an [`ApiError`]
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// [`axum::extract::Query`] whose rejection is an [`ApiError`]
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// Middleware: assign the request's correlation id.
+///
+/// Reuses the caller's `X-Request-Id` when it is reasonable, otherwise
+/// generates one, makes it available to [`ApiError`] responses and echoes it
+/// on the response.
+pub async fn request_id(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn kube_api_error(code: u16) -> kube::Error {
+        kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: "boom".to_string(),
+            reason: "Test".to_string(),
+            code,
+        })
+    }
+
+    #[test]
+    fn test_claim_error_mapping() {
+        let cases = [
+            (ClaimError::InvalidSpec("x".into()), StatusCode::BAD_REQUEST),
+            (ClaimError::EmptyUpdate("x".into()), StatusCode::BAD_REQUEST),
+            (ClaimError::UnknownTenant("x".into()), StatusCode::FORBIDDEN),
+            (ClaimError::NotFound("x".into()), StatusCode::NOT_FOUND),
+            (ClaimError::NameCollision("x".into()), StatusCode::CONFLICT),
+            (
+                ClaimError::NotDeleted {
+                    name: "x".into(),
+                    timeout: Duration::from_secs(1),
+                },
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+        ];
+
+        for (claim, status) in cases {
+            assert_eq!(ApiError::from(claim).status(), status);
+        }
+    }
+
+    #[test]
+    fn test_anyhow_downcasts_through_context() {
+        let err = Err::<(), _>(ClaimError::NotFound("mem-alice-bot".into()))
+            .context("Failed to update claim")
+            .unwrap_err();
+        assert!(matches!(ApiError::from(err), ApiError::NotFound(_)));
+
+        let err = anyhow::Error::from(kube_api_error(409)).context("Failed to create claim");
+        assert!(matches!(ApiError::from(err), ApiError::Conflict(_)));
+
+        let err = anyhow::Error::from(CloudflareApiError::Conflict("record exists".into()))
+            .context("Failed to create DNS record");
+        assert_eq!(ApiError::from(err).status(), StatusCode::CONFLICT);
+
+        let err = anyhow::Error::from(CloudflareApiError::Invalid("invalid zone".into()));
+        assert_eq!(ApiError::from(err).status(), StatusCode::BAD_REQUEST);
+
+        let err = anyhow::Error::from(CloudflareApiError::Upstream {
+            status: 500,
+            message: "internal".into(),
+        });
+        let err = ApiError::from(err);
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+        assert!(err.is_retryable());
+
+        let err = anyhow::anyhow!("something else");
+        assert_eq!(ApiError::from(err).status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_kube_error_mapping() {
+        assert_eq!(ApiError::from(kube_api_error(404)).code(), "not_found");
+        assert_eq!(ApiError::from(kube_api_error(422)).code(), "invalid_request");
+        assert_eq!(ApiError::from(kube_api_error(500)).code(), "internal");
+    }
+
+    #[test]
+    fn test_not_configured() {
+        let err = ApiError::NotConfigured("CloudflareTool");
+        assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(err.code(), "not_configured");
+        assert!(!err.is_retryable());
+        assert!(ApiError::Timeout("slow".into()).is_retryable());
+    }
+
+    async fn failing() -> Result<(), ApiError> {
+        Err(ApiError::NotFound("Claim mem-alice-bot not found".into()))
+    }
+
+    async fn call(request_id: Option<&str>) -> (StatusCode, Option<String>, serde_json::Value) {
+        let app = Router::new()
+            .route("/fail", get(failing))
+            .layer(middleware::from_fn(super::request_id));
+
+        let mut req = Request::builder().uri("/fail");
+        if let Some(id) = request_id {
+            req = req.header(REQUEST_ID_HEADER, id);
+        }
+        let response = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+
+        let status = response.status();
+        let header = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, header, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Replicas {
+        replicas: u8,
+    }
+
+    async fn typed(
+        ApiPath(_id): ApiPath<uuid::Uuid>,
+        ApiQuery(_query): ApiQuery<Replicas>,
+        ApiJson(_body): ApiJson<Replicas>,
+    ) {
+    }
+
+    #[tokio::test]
+    async fn test_extractor_rejections_are_api_errors() {
+        let app = Router::new()
+            .route("/jobs/:id", axum::routing::post(typed))
+            .layer(middleware::from_fn(super::request_id));
+        let id = uuid::Uuid::new_v4();
+
+        let cases = [
+            ("/jobs/not-a-uuid?replicas=1".to_string(), r#"{"replicas": 1}"#),
+            (format!("/jobs/{}?replicas=many", id), r#"{"replicas": 1}"#),
+            (format!("/jobs/{}?replicas=1", id), r#"{"replicas": 300}"#),
+            (format!("/jobs/{}?replicas=1", id), r#"{"replicas": 1, "gpu": true}"#),
+        ];
+        for (uri, body) in cases {
+            let req = Request::post(&uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{} {}", uri, body);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], "invalid_request");
+            assert!(body["request_id"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_carries_caller_request_id() {
+        let (status, header, body) = call(Some("req-42")).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(header.as_deref(), Some("req-42"));
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["request_id"], "req-42");
+        assert_eq!(body["retryable"], false);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_generated_when_missing() {
+        let (_, header, body) = call(None).await;
+
+        let header = header.unwrap();
+        assert!(uuid::Uuid::parse_str(&header).is_ok());
+        assert_eq!(body["request_id"], header.as_str());
+    }
+}
@@ -6,24 +6,48 @@
 #![allow(dead_code)]
 use anyhow::{Context, Result};
 use axum::{
-    extract::State,
-    routing::{get, post},
+    async_trait,
+    extract::{FromRequestParts, State},
+    http::{request::Parts, StatusCode},
+    middleware,
+    routing::{get, patch, post},
     Json, Router,
 };
 use clap::{Parser, Subcommand};
+use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
 use tower_http::cors::CorsLayer;
 use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
 mod agents;
+mod auth;
+mod error;
+mod metrics;
+mod openapi;
 mod tools;
 
-use agents::executor::CrossplaneExecutor;
+use agents::executor::{
+    AgentMemory, AgentMemorySpec, AgentMemorySpecPatch, AgentWorker, AgentWorkerSpec,
+    AgentWorkerSpecPatch, Claim, CrossplaneExecutor, GpuType, MemoryType, Provider,
+    ProvisionJob, ProvisionJobs, Replicas, StorageSize, Tenant, TenantConfig, Tier,
+};
 use agents::cherry_pick::CherryPickAgent;
+use auth::{
+    require_scope, Authenticator, Identity, SCOPE_DNS_READ, SCOPE_DNS_WRITE,
+    SCOPE_PROVISION_MEMORY, SCOPE_PROVISION_READ, SCOPE_PROVISION_WORKER,
+};
+use error::{ApiError, ApiJson, ApiPath, ApiQuery, ErrorBody};
 use tools::CloudflareTool;
 
+/// Seconds to let in-flight requests finish after a shutdown signal.
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 25;
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
@@ -47,11 +71,19 @@ enum Commands {
         #[arg(long)]
         branch: String,
     },
+    /// Write the HTTP API's OpenAPI document
+    Openapi {
+        /// Output file (stdout if omitted)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(Clone)]
 struct AppState {
     executor: Arc<CrossplaneExecutor>,
+    tenants: Arc<TenantConfig>,
+    jobs: ProvisionJobs,
     cloudflare: Option<Arc<CloudflareTool>>,
 }
 
@@ -68,6 +100,7 @@ async fn main() -> Result<()> {
         Commands::Server => run_server().await,
         Commands::TrainCherryPick { depth } => run_train_cherry_pick(depth).await,
         Commands::CherryPick { commit, branch } => run_cherry_pick(commit, branch).await,
+        Commands::Openapi { output } => write_openapi(output),
     }
 }
 
@@ -96,6 +129,21 @@ async fn run_cherry_pick(commit: String, branch: String) -> Result<()> {
     Ok(())
 }
 
+fn write_openapi(output: Option<PathBuf>) -> Result<()> {
+    let document = openapi::ApiDoc::openapi().to_pretty_json()?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, document + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            info!("OpenAPI document written to {}", path.display());
+        }
+        None => println!("{}", document),
+    }
+
+    Ok(())
+}
+
 async fn create_cherry_pick_agent() -> Result<CherryPickAgent> {
     let repo_path = std::env::current_dir()?;
     let qdrant_url = std::env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6333".to_string());
@@ -109,7 +157,16 @@ async fn run_server() -> Result<()> {
     info!("Starting Lornu AI Engine Server");
 
     // Initialize Crossplane executor
-    let executor = Arc::new(CrossplaneExecutor::new().await?);
+    let ready_timeout = std::env::var("CLAIM_READY_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(agents::executor::DEFAULT_READY_TIMEOUT);
+    let executor = Arc::new(
+        CrossplaneExecutor::new()
+            .await?
+            .with_ready_timeout(ready_timeout),
+    );
 
     // Initialize Cloudflare tool (optional - requires LORNU_GCP_PROJECT)
     let cloudflare = match CloudflareTool::new() {
@@ -123,15 +180,66 @@ async fn run_server() -> Result<()> {
         }
     };
 
-    let state = AppState { executor, cloudflare };
+    let tenants = Arc::new(TenantConfig::from_env()?);
+    let prometheus = metrics::install()?;
+    let authenticator = Arc::new(Authenticator::from_env().await?);
+    tokio::spawn(authenticator.clone().refresh_periodically());
 
-    let app = Router::new()
-        .route("/health", get(health_check))
+    let state = AppState {
+        executor,
+        tenants,
+        jobs: ProvisionJobs::new(),
+        cloudflare,
+    };
+
+    let memory_routes = Router::new()
         .route("/api/provision/memory", post(provision_memory))
+        .route(
+            "/api/provision/memory/:name",
+            patch(update_memory).delete(deprovision_memory),
+        )
+        .route_layer(middleware::from_fn_with_state(SCOPE_PROVISION_MEMORY, require_scope));
+
+    let worker_routes = Router::new()
         .route("/api/provision/worker", post(provision_worker))
+        .route(
+            "/api/provision/worker/:name",
+            patch(update_worker).delete(deprovision_worker),
+        )
+        .route_layer(middleware::from_fn_with_state(SCOPE_PROVISION_WORKER, require_scope));
+
+    let status_routes = Router::new()
+        .route("/api/provision/jobs/:id", get(provision_job_status))
         .route("/api/agents/status", get(agent_status))
-        .route("/api/dns/create", post(create_dns_record))
-        .route("/api/dns/list", get(list_dns_records))
+        .route_layer(middleware::from_fn_with_state(SCOPE_PROVISION_READ, require_scope));
+
+    let dns_routes = Router::new()
+        .route(
+            "/api/dns/create",
+            post(create_dns_record)
+                .route_layer(middleware::from_fn_with_state(SCOPE_DNS_WRITE, require_scope)),
+        )
+        .route(
+            "/api/dns/list",
+            get(list_dns_records)
+                .route_layer(middleware::from_fn_with_state(SCOPE_DNS_READ, require_scope)),
+        );
+
+    // Scope checks run inside authentication, which attaches the caller's Identity
+    let api_routes = Router::new()
+        .merge(memory_routes)
+        .merge(worker_routes)
+        .merge(status_routes)
+        .merge(dns_routes)
+        .route_layer(middleware::from_fn_with_state(authenticator, auth::authenticate));
+
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/api/openapi.json", get(openapi::serve))
+        .route("/metrics", get(move || std::future::ready(prometheus.render())))
+        .merge(api_routes)
+        .layer(middleware::from_fn(metrics::track_requests))
+        .layer(middleware::from_fn(error::request_id))
         .layer(CorsLayer::permissive())
         .with_state(state);
 
@@ -139,175 +247,589 @@ async fn run_server() -> Result<()> {
     info!("Engine listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let (signal_tx, signal_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = signal_tx.send(true);
+    });
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown(signalled(signal_rx.clone()))
+        .into_future();
+
+    // Long-lived connections (streams, WebSockets) must not hold up shutdown
+    let grace = shutdown_grace();
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            signalled(signal_rx).await;
+            tokio::time::sleep(grace).await;
+        } => warn!("Grace period of {}s expired; closing open connections", grace.as_secs()),
+    }
 
+    info!("Shutdown complete");
     Ok(())
 }
 
-async fn health_check() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "service": "lornu-engine",
-        "version": env!("CARGO_PKG_VERSION")
-    }))
+/// Resolves on SIGTERM or Ctrl+C.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, finishing in-flight requests");
+}
+
+/// Seconds to let in-flight requests finish after a shutdown signal, from
+/// `SHUTDOWN_GRACE_SECS`.
+fn shutdown_grace() -> Duration {
+    let secs = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Resolves once the shutdown signal has been received.
+async fn signalled(mut signal: watch::Receiver<bool>) {
+    let _ = signal.wait_for(|received| *received).await;
 }
 
-#[derive(serde::Deserialize)]
+/// The caller's tenant, resolved from the `tenant` claim of their token.
+///
+/// Callers without a tenant claim fall back to the configured default tenant.
+struct CallerTenant(Tenant);
+
+#[async_trait]
+impl FromRequestParts<AppState> for CallerTenant {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let identity = Identity::from_request_parts(parts, state).await?;
+
+        Ok(CallerTenant(state.tenants.resolve(identity.tenant.as_deref())?))
+    }
+}
+
+/// Service health
+#[derive(serde::Serialize, ToSchema)]
+struct HealthResponse {
+    #[schema(example = "healthy")]
+    status: String,
+    #[schema(example = "lornu-engine")]
+    service: String,
+    version: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses((status = 200, description = "Engine is up", body = HealthResponse))
+)]
+async fn health_check() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "healthy".to_string(),
+        service: "lornu-engine".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+/// Provision memory (a database) for one of the caller's agents
+#[derive(serde::Deserialize, ToSchema)]
 struct ProvisionMemoryRequest {
-    user: String,
+    /// Agent name (lowercase RFC 1123 label)
+    #[schema(example = "summarizer")]
     agent: String,
-    #[serde(default = "default_provider")]
-    provider: String,
-    #[serde(default = "default_memory_type")]
-    memory_type: String,
-    #[serde(default = "default_size")]
-    size: String,
+    #[serde(default)]
+    #[schema(default = "gcp")]
+    provider: Provider,
+    #[serde(default)]
+    #[schema(default = "postgres")]
+    memory_type: MemoryType,
+    #[serde(default)]
+    size: StorageSize,
+    #[serde(default)]
+    #[schema(default = "small")]
+    tier: Tier,
 }
 
-fn default_provider() -> String { "gcp".to_string() }
-fn default_memory_type() -> String { "postgres".to_string() }
-fn default_size() -> String { "10Gi".to_string() }
-
+#[utoipa::path(
+    post,
+    path = "/api/provision/memory",
+    tag = "provisioning",
+    request_body = ProvisionMemoryRequest,
+    responses(
+        (status = 202, description = "Claim created; poll `status_url` for readiness", body = ProvisionAccepted),
+        (status = 400, description = "Invalid agent name or spec", body = ErrorBody),
+        (status = 409, description = "Claim name used by another agent", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:memory"]))
+)]
 async fn provision_memory(
     State(state): State<AppState>,
-    Json(req): Json<ProvisionMemoryRequest>,
-) -> Json<serde_json::Value> {
-    match state.executor.provision_agent_memory(
-        &req.user,
-        &req.agent,
-        &req.provider,
-        &req.memory_type,
-        &req.size,
-    ).await {
-        Ok(name) => Json(serde_json::json!({
-            "status": "provisioned",
-            "claim_name": name
-        })),
-        Err(e) => Json(serde_json::json!({
-            "status": "error",
-            "message": e.to_string()
-        })),
-    }
+    CallerTenant(tenant): CallerTenant,
+    identity: Identity,
+    ApiJson(req): ApiJson<ProvisionMemoryRequest>,
+) -> Result<(StatusCode, Json<ProvisionAccepted>), ApiError> {
+    let spec = AgentMemorySpec {
+        provider: req.provider,
+        memory_type: req.memory_type,
+        size: req.size,
+        tier: req.tier,
+    };
+
+    let name = state
+        .executor
+        .create_agent_memory_claim(&tenant, &identity.user, &req.agent, spec)
+        .await?;
+
+    Ok(accept_provision_job::<AgentMemory>(&state, tenant, name))
 }
 
-#[derive(serde::Deserialize)]
+/// Provision a worker (compute) for one of the caller's agents
+#[derive(serde::Deserialize, ToSchema)]
 struct ProvisionWorkerRequest {
-    user: String,
+    /// Agent name (lowercase RFC 1123 label)
+    #[schema(example = "summarizer")]
     agent: String,
     #[serde(default)]
+    #[schema(default = "gcp")]
+    provider: Provider,
+    /// Attach a GPU
+    #[serde(default)]
+    #[schema(default = false)]
     gpu: bool,
-    gpu_type: Option<String>,
-    #[serde(default = "default_replicas")]
-    replicas: i32,
+    /// GPU model, when `gpu` is set
+    gpu_type: Option<GpuType>,
+    #[serde(default)]
+    replicas: Replicas,
 }
 
-fn default_replicas() -> i32 { 1 }
-
+#[utoipa::path(
+    post,
+    path = "/api/provision/worker",
+    tag = "provisioning",
+    request_body = ProvisionWorkerRequest,
+    responses(
+        (status = 202, description = "Claim created; poll `status_url` for readiness", body = ProvisionAccepted),
+        (status = 400, description = "Invalid agent name or spec", body = ErrorBody),
+        (status = 409, description = "Claim name used by another agent", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:worker"]))
+)]
 async fn provision_worker(
     State(state): State<AppState>,
-    Json(req): Json<ProvisionWorkerRequest>,
-) -> Json<serde_json::Value> {
-    match state.executor.provision_agent_worker(
-        &req.user,
-        &req.agent,
-        req.gpu,
-        req.gpu_type.as_deref(),
-        req.replicas,
-    ).await {
-        Ok(name) => Json(serde_json::json!({
-            "status": "provisioned",
-            "claim_name": name
-        })),
-        Err(e) => Json(serde_json::json!({
-            "status": "error",
-            "message": e.to_string()
-        })),
-    }
+    CallerTenant(tenant): CallerTenant,
+    identity: Identity,
+    ApiJson(req): ApiJson<ProvisionWorkerRequest>,
+) -> Result<(StatusCode, Json<ProvisionAccepted>), ApiError> {
+    let spec = AgentWorkerSpec {
+        provider: req.provider,
+        gpu: req.gpu,
+        gpu_type: req.gpu_type,
+        replicas: req.replicas,
+        timeout: "30m".to_string(),
+    };
+
+    let name = state
+        .executor
+        .create_agent_worker_claim(&tenant, &identity.user, &req.agent, spec)
+        .await?;
+
+    Ok(accept_provision_job::<AgentWorker>(&state, tenant, name))
 }
 
-async fn agent_status(State(state): State<AppState>) -> Json<serde_json::Value> {
-    match state.executor.list_agent_resources().await {
-        Ok(resources) => Json(serde_json::json!({
-            "status": "ok",
-            "resources": resources
-        })),
-        Err(e) => Json(serde_json::json!({
-            "status": "error",
-            "message": e.to_string()
-        })),
-    }
+/// A claim was created and is being provisioned in the background
+#[derive(serde::Serialize, ToSchema)]
+struct ProvisionAccepted {
+    #[schema(example = "accepted")]
+    status: String,
+    job_id: uuid::Uuid,
+    #[schema(example = "mem-alice-summarizer")]
+    claim_name: String,
+    /// Where to poll for the job's progress
+    status_url: String,
+}
+
+/// Register a job for a freshly created claim, wait for readiness in the
+/// background and answer `202 Accepted` with the job id.
+fn accept_provision_job<K: Claim>(
+    state: &AppState,
+    tenant: Tenant,
+    name: String,
+) -> (StatusCode, Json<ProvisionAccepted>) {
+    let kind = K::kind(&());
+    let job = state.jobs.create(&tenant.name, &kind, &name);
+
+    let executor = state.executor.clone();
+    let jobs = state.jobs.clone();
+    let job_id = job.id;
+    let started = std::time::Instant::now();
+    tokio::spawn(async move {
+        let outcome = executor
+            .wait_for_ready::<K>(&tenant, &name, |conditions| {
+                jobs.update_conditions(&job_id, conditions)
+            })
+            .await;
+        if let Err(e) = &outcome {
+            warn!("Provisioning job {} for {} failed: {}", job_id, name, e);
+        }
+        metrics::record_provision(&kind, outcome.is_ok(), started.elapsed());
+        jobs.finish(&job_id, outcome);
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        Json(ProvisionAccepted {
+            status: "accepted".to_string(),
+            job_id: job.id,
+            claim_name: job.claim_name,
+            status_url: format!("/api/provision/jobs/{}", job.id),
+        }),
+    )
+}
+
+/// Progress of a provisioning job
+#[derive(serde::Serialize, ToSchema)]
+struct JobStatusResponse {
+    #[schema(example = "ok")]
+    status: String,
+    job: ProvisionJob,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/provision/jobs/{id}",
+    tag = "provisioning",
+    params(("id" = uuid::Uuid, Path, description = "Job id returned when provisioning")),
+    responses(
+        (status = 200, description = "Job progress", body = JobStatusResponse),
+        (status = 404, description = "No such job for this tenant", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:read"]))
+)]
+async fn provision_job_status(
+    State(state): State<AppState>,
+    CallerTenant(tenant): CallerTenant,
+    ApiPath(id): ApiPath<uuid::Uuid>,
+) -> Result<Json<JobStatusResponse>, ApiError> {
+    let job = state
+        .jobs
+        .get(&tenant.name, &id)
+        .ok_or_else(|| ApiError::NotFound(format!("Provisioning job {} not found", id)))?;
+
+    Ok(Json(JobStatusResponse {
+        status: "ok".to_string(),
+        job,
+    }))
+}
+
+/// Resize an `AgentMemory` claim
+#[derive(serde::Deserialize, ToSchema)]
+struct UpdateMemoryRequest {
+    size: Option<StorageSize>,
+}
+
+/// Outcome of an update or delete on a claim
+#[derive(serde::Serialize, ToSchema)]
+struct ClaimResponse {
+    /// `updated`, `deleting` or `deleted`
+    #[schema(example = "updated")]
+    status: String,
+    #[schema(example = "mem-alice-summarizer")]
+    claim_name: String,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/provision/memory/{name}",
+    tag = "provisioning",
+    params(("name" = String, Path, description = "Claim name")),
+    request_body = UpdateMemoryRequest,
+    responses(
+        (status = 200, description = "Claim updated", body = ClaimResponse),
+        (status = 400, description = "Invalid or empty update", body = ErrorBody),
+        (status = 404, description = "No such claim for this tenant", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:memory"]))
+)]
+async fn update_memory(
+    State(state): State<AppState>,
+    CallerTenant(tenant): CallerTenant,
+    ApiPath(name): ApiPath<String>,
+    ApiJson(req): ApiJson<UpdateMemoryRequest>,
+) -> Result<Json<ClaimResponse>, ApiError> {
+    let patch = AgentMemorySpecPatch { size: req.size };
+
+    state.executor.update_agent_memory(&tenant, &name, patch).await?;
+
+    Ok(claim_response("updated", name))
+}
+
+/// Change GPU or replica settings of an `AgentWorker` claim
+#[derive(serde::Deserialize, ToSchema)]
+struct UpdateWorkerRequest {
+    gpu: Option<bool>,
+    gpu_type: Option<GpuType>,
+    replicas: Option<Replicas>,
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/provision/worker/{name}",
+    tag = "provisioning",
+    params(("name" = String, Path, description = "Claim name")),
+    request_body = UpdateWorkerRequest,
+    responses(
+        (status = 200, description = "Claim updated", body = ClaimResponse),
+        (status = 400, description = "Invalid or empty update", body = ErrorBody),
+        (status = 404, description = "No such claim for this tenant", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:worker"]))
+)]
+async fn update_worker(
+    State(state): State<AppState>,
+    CallerTenant(tenant): CallerTenant,
+    ApiPath(name): ApiPath<String>,
+    ApiJson(req): ApiJson<UpdateWorkerRequest>,
+) -> Result<Json<ClaimResponse>, ApiError> {
+    let patch = AgentWorkerSpecPatch {
+        gpu: req.gpu,
+        gpu_type: req.gpu_type,
+        replicas: req.replicas,
+    };
+
+    state.executor.update_agent_worker(&tenant, &name, patch).await?;
+
+    Ok(claim_response("updated", name))
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeprovisionQuery {
+    /// Wait for Crossplane finalizers to clear before responding
+    #[serde(default)]
+    wait: bool,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/provision/memory/{name}",
+    tag = "provisioning",
+    params(("name" = String, Path, description = "Claim name"), DeprovisionQuery),
+    responses(
+        (status = 200, description = "Claim deleting (or deleted, with `wait`)", body = ClaimResponse),
+        (status = 404, description = "No such claim for this tenant", body = ErrorBody),
+        (status = 504, description = "Finalizers did not clear in time", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:memory"]))
+)]
+async fn deprovision_memory(
+    State(state): State<AppState>,
+    CallerTenant(tenant): CallerTenant,
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<DeprovisionQuery>,
+) -> Result<Json<ClaimResponse>, ApiError> {
+    deprovision::<AgentMemory>(&state, &tenant, name, query.wait).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/provision/worker/{name}",
+    tag = "provisioning",
+    params(("name" = String, Path, description = "Claim name"), DeprovisionQuery),
+    responses(
+        (status = 200, description = "Claim deleting (or deleted, with `wait`)", body = ClaimResponse),
+        (status = 404, description = "No such claim for this tenant", body = ErrorBody),
+        (status = 504, description = "Finalizers did not clear in time", body = ErrorBody),
+    ),
+    security(("bearer" = ["provision:worker"]))
+)]
+async fn deprovision_worker(
+    State(state): State<AppState>,
+    CallerTenant(tenant): CallerTenant,
+    ApiPath(name): ApiPath<String>,
+    ApiQuery(query): ApiQuery<DeprovisionQuery>,
+) -> Result<Json<ClaimResponse>, ApiError> {
+    deprovision::<AgentWorker>(&state, &tenant, name, query.wait).await
+}
+
+async fn deprovision<K: Claim>(
+    state: &AppState,
+    tenant: &Tenant,
+    name: String,
+    wait: bool,
+) -> Result<Json<ClaimResponse>, ApiError> {
+    state.executor.delete_claim::<K>(tenant, &name, wait).await?;
+
+    Ok(claim_response(if wait { "deleted" } else { "deleting" }, name))
+}
+
+fn claim_response(status: &str, claim_name: String) -> Json<ClaimResponse> {
+    Json(ClaimResponse {
+        status: status.to_string(),
+        claim_name,
+    })
+}
+
+/// The tenant's claims and their conditions
+#[derive(serde::Serialize, ToSchema)]
+struct AgentStatusResponse {
+    #[schema(example = "ok")]
+    status: String,
+    /// `{kind, name, status: {conditions}}` per claim
+    resources: Vec<serde_json::Value>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/agents/status",
+    tag = "provisioning",
+    responses((status = 200, description = "Claims in the caller's tenant", body = AgentStatusResponse)),
+    security(("bearer" = ["provision:read"]))
+)]
+async fn agent_status(
+    State(state): State<AppState>,
+    CallerTenant(tenant): CallerTenant,
+) -> Result<Json<AgentStatusResponse>, ApiError> {
+    let resources = state.executor.list_agent_resources(&tenant).await?;
+
+    Ok(Json(AgentStatusResponse {
+        status: "ok".to_string(),
+        resources,
+    }))
 }
 
 // ============================================================================
 // DNS Endpoints (CloudflareTool)
 // ============================================================================
 
-#[derive(serde::Deserialize)]
+/// Create a DNS record in a Cloudflare zone
+#[derive(serde::Deserialize, ToSchema)]
 struct CreateDnsRequest {
+    /// Zone to create the record in (defaults to `CLOUDFLARE_ZONE_ID`)
     zone_id: Option<String>,
+    /// Record name
+    #[schema(example = "agent.lornu.ai")]
     name: String,
+    /// Record content (target address)
+    #[schema(example = "203.0.113.10")]
     content: String,
+    /// Proxy traffic through Cloudflare
     #[serde(default = "default_proxied")]
+    #[schema(default = true)]
     proxied: bool,
 }
 
 fn default_proxied() -> bool { true }
 
+/// The Cloudflare tool, or `503` if this engine was started without it.
+fn cloudflare(state: &AppState) -> Result<&CloudflareTool, ApiError> {
+    state
+        .cloudflare
+        .as_deref()
+        .ok_or(ApiError::NotConfigured("CloudflareTool"))
+}
+
+/// A DNS record was created
+#[derive(serde::Serialize, ToSchema)]
+struct DnsRecordCreated {
+    #[schema(example = "created")]
+    status: String,
+    record_id: String,
+    name: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/dns/create",
+    tag = "dns",
+    request_body = CreateDnsRequest,
+    responses(
+        (status = 200, description = "Record created", body = DnsRecordCreated),
+        (status = 400, description = "Cloudflare rejected the record or zone", body = ErrorBody),
+        (status = 409, description = "The record already exists", body = ErrorBody),
+        (status = 502, description = "Cloudflare failed", body = ErrorBody),
+        (status = 503, description = "Cloudflare is not configured on this engine", body = ErrorBody),
+    ),
+    security(("bearer" = ["dns:write"]))
+)]
 async fn create_dns_record(
     State(state): State<AppState>,
-    Json(req): Json<CreateDnsRequest>,
-) -> Json<serde_json::Value> {
-    let cloudflare = match &state.cloudflare {
-        Some(cf) => cf,
-        None => return Json(serde_json::json!({
-            "status": "error",
-            "message": "CloudflareTool not configured (set LORNU_GCP_PROJECT)"
-        })),
-    };
+    ApiJson(req): ApiJson<CreateDnsRequest>,
+) -> Result<Json<DnsRecordCreated>, ApiError> {
+    let cloudflare = cloudflare(&state)?;
 
-    match cloudflare.create_dns_record(
+    let record_id = cloudflare.create_dns_record(
         req.zone_id.as_deref(),
         &req.name,
         &req.content,
         req.proxied,
-    ).await {
-        Ok(record_id) => Json(serde_json::json!({
-            "status": "created",
-            "record_id": record_id,
-            "name": req.name
-        })),
-        Err(e) => Json(serde_json::json!({
-            "status": "error",
-            "message": e.to_string()
-        })),
-    }
+    ).await?;
+
+    Ok(Json(DnsRecordCreated {
+        status: "created".to_string(),
+        record_id,
+        name: req.name,
+    }))
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ListDnsRequest {
+    /// Zone to list (defaults to `CLOUDFLARE_ZONE_ID`)
     zone_id: Option<String>,
 }
 
+/// DNS records in a zone
+#[derive(serde::Serialize, ToSchema)]
+struct DnsRecordList {
+    #[schema(example = "ok")]
+    status: String,
+    /// Records as returned by the Cloudflare API
+    records: Vec<serde_json::Value>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/dns/list",
+    tag = "dns",
+    params(ListDnsRequest),
+    responses(
+        (status = 200, description = "Records in the zone", body = DnsRecordList),
+        (status = 400, description = "Cloudflare rejected the zone", body = ErrorBody),
+        (status = 502, description = "Cloudflare failed", body = ErrorBody),
+        (status = 503, description = "Cloudflare is not configured on this engine", body = ErrorBody),
+    ),
+    security(("bearer" = ["dns:read"]))
+)]
 async fn list_dns_records(
     State(state): State<AppState>,
-    axum::extract::Query(query): axum::extract::Query<ListDnsRequest>,
-) -> Json<serde_json::Value> {
-    let cloudflare = match &state.cloudflare {
-        Some(cf) => cf,
-        None => return Json(serde_json::json!({
-            "status": "error",
-            "message": "CloudflareTool not configured"
-        })),
-    };
-
-    match cloudflare.list_dns_records(query.zone_id.as_deref()).await {
-        Ok(records) => Json(serde_json::json!({
-            "status": "ok",
-            "records": records
-        })),
-        Err(e) => Json(serde_json::json!({
-            "status": "error",
-            "message": e.to_string()
-        })),
-    }
+    ApiQuery(query): ApiQuery<ListDnsRequest>,
+) -> Result<Json<DnsRecordList>, ApiError> {
+    let records = cloudflare(&state)?
+        .list_dns_records(query.zone_id.as_deref())
+        .await?;
+
+    Ok(Json(DnsRecordList {
+        status: "ok".to_string(),
+        records,
+    }))
 }
@@ -0,0 +1,50 @@
+//! Metrics
+//!
+//! Prometheus metrics for the engine, exposed at `/metrics`. HTTP requests are
+//! counted and timed per matched route; provisioning jobs and Cloudflare calls
+//! record their own series where they happen.
+
+use anyhow::Result;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::time::Duration;
+
+pub use lornu_http_metrics::track_requests;
+
+/// Buckets (seconds) for claims becoming Ready, which takes minutes.
+const PROVISION_BUCKETS: [f64; 9] = [5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0];
+
+/// Time from claim creation to Ready (or failure), by claim kind and outcome.
+pub const PROVISION_DURATION: &str = "engine_provision_duration_seconds";
+
+/// Install the global Prometheus recorder.
+pub fn install() -> Result<PrometheusHandle> {
+    lornu_http_metrics::install(&[(PROVISION_DURATION, &PROVISION_BUCKETS)])
+}
+
+/// Record how long a claim took to become Ready, or to fail.
+pub fn record_provision(kind: &str, ready: bool, elapsed: Duration) {
+    let outcome = if ready { "ready" } else { "failed" };
+    metrics::histogram!(PROVISION_DURATION, "kind" => kind.to_string(), "outcome" => outcome)
+        .record(elapsed.as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    fn handle() -> &'static PrometheusHandle {
+        static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+        HANDLE.get_or_init(|| install().unwrap())
+    }
+
+    #[test]
+    fn test_provision_buckets() {
+        let handle = handle();
+        record_provision("AgentWorker", true, Duration::from_secs(90));
+
+        assert!(handle.render().contains(
+            r#"engine_provision_duration_seconds_bucket{kind="AgentWorker",outcome="ready",le="120"} 1"#
+        ));
+    }
+}
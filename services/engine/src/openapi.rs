@@ -0,0 +1,129 @@
+//! OpenAPI Document
+//!
+//! OpenAPI 3 description of the engine HTTP API, generated from the route
+//! handlers and their request/response types. Served at `/api/openapi.json`
+//! and written by `engine openapi --output <file>` for client generation.
+
+use axum::Json;
+use std::sync::LazyLock;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::agents::executor::{ClaimCondition, GpuType, JobPhase, MemoryType, Provider, Replicas, StorageSize, Tier};
+use crate::error::ErrorBody;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Lornu AI Engine", description = "Agent provisioning and tool API"),
+    paths(
+        crate::health_check,
+        crate::provision_memory,
+        crate::provision_worker,
+        crate::update_memory,
+        crate::update_worker,
+        crate::deprovision_memory,
+        crate::deprovision_worker,
+        crate::provision_job_status,
+        crate::agent_status,
+        crate::create_dns_record,
+        crate::list_dns_records,
+    ),
+    components(schemas(
+        ErrorBody,
+        ClaimCondition,
+        JobPhase,
+        Provider,
+        MemoryType,
+        Tier,
+        GpuType,
+        StorageSize,
+        Replicas,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "health", description = "Liveness"),
+        (name = "provisioning", description = "AgentMemory and AgentWorker claims"),
+        (name = "dns", description = "Cloudflare DNS records"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the `bearer` security scheme referenced by the route scopes.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.components.get_or_insert_with(Default::default).add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+static DOCUMENT: LazyLock<utoipa::openapi::OpenApi> = LazyLock::new(ApiDoc::openapi);
+
+/// `GET /api/openapi.json`
+pub async fn serve() -> Json<utoipa::openapi::OpenApi> {
+    Json(DOCUMENT.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> serde_json::Value {
+        serde_json::to_value(ApiDoc::openapi()).unwrap()
+    }
+
+    #[test]
+    fn test_documents_every_route() {
+        let doc = document();
+        let paths = doc["paths"].as_object().unwrap();
+
+        for (path, method) in [
+            ("/health", "get"),
+            ("/api/provision/memory", "post"),
+            ("/api/provision/worker", "post"),
+            ("/api/provision/memory/{name}", "patch"),
+            ("/api/provision/memory/{name}", "delete"),
+            ("/api/provision/worker/{name}", "patch"),
+            ("/api/provision/worker/{name}", "delete"),
+            ("/api/provision/jobs/{id}", "get"),
+            ("/api/agents/status", "get"),
+            ("/api/dns/create", "post"),
+            ("/api/dns/list", "get"),
+        ] {
+            assert!(paths[path][method].is_object(), "{} {} is not documented", method, path);
+        }
+    }
+
+    #[test]
+    fn test_request_defaults_are_documented() {
+        let doc = document();
+        let schemas = &doc["components"]["schemas"];
+
+        let memory = &schemas["ProvisionMemoryRequest"];
+        assert_eq!(memory["required"], serde_json::json!(["agent"]));
+        assert_eq!(schemas["StorageSize"]["default"], "10Gi");
+
+        let dns = &schemas["CreateDnsRequest"];
+        assert_eq!(dns["properties"]["proxied"]["default"], true);
+        assert_eq!(schemas["Replicas"]["maximum"], 10);
+    }
+
+    #[test]
+    fn test_routes_declare_scopes() {
+        let doc = document();
+
+        assert_eq!(
+            doc["paths"]["/api/dns/create"]["post"]["security"][0]["bearer"],
+            serde_json::json!(["dns:write"])
+        );
+        assert_eq!(doc["components"]["securitySchemes"]["bearer"]["scheme"], "bearer");
+    }
+}
@@ -12,9 +12,11 @@
 //! - **Runtime Injection**: `LORNU_GCP_PROJECT` is injected by K8s, not hardcoded
 
 use anyhow::{Context, Result};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::time::Instant;
 use tracing::info;
 
 /// Cloudflare DNS management tool with secure credential handling.
@@ -56,6 +58,58 @@ struct CloudflareError {
     message: String,
 }
 
+/// Cloudflare error codes for a record that already exists
+const DUPLICATE_RECORD_CODES: [i32; 3] = [81053, 81057, 81058];
+
+/// A request Cloudflare answered with an error, by whose fault it was
+#[derive(Debug, thiserror::Error)]
+pub enum CloudflareApiError {
+    /// The request itself was refused, e.g. an unknown zone or invalid content
+    #[error("Cloudflare rejected the request: {0}")]
+    Invalid(String),
+
+    /// The record already exists
+    #[error("Cloudflare rejected the request: {0}")]
+    Conflict(String),
+
+    /// Cloudflare failed, rate limited the engine or refused its credentials
+    #[error("Cloudflare API failed ({status}): {message}")]
+    Upstream { status: u16, message: String },
+}
+
+impl CloudflareApiError {
+    /// Classify a response with `success: false` or an error status.
+    fn from_response(status: StatusCode, errors: &[CloudflareError]) -> Self {
+        let message = if errors.is_empty() {
+            status.to_string()
+        } else {
+            errors
+                .iter()
+                .map(|e| format!("{} ({})", e.message, e.code))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let duplicate = errors.iter().any(|e| DUPLICATE_RECORD_CODES.contains(&e.code));
+        if status == StatusCode::CONFLICT || duplicate {
+            return Self::Conflict(message);
+        }
+        match status {
+            // Credentials and rate limits are the engine's problem, not the caller's
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => Self::Upstream {
+                status: status.as_u16(),
+                message,
+            },
+            // `success: false` arrives with a 4xx, or occasionally a 200
+            _ if status.is_client_error() || status.is_success() => Self::Invalid(message),
+            _ => Self::Upstream {
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct DnsRecordResult {
     id: String,
@@ -246,30 +300,11 @@ impl CloudflareTool {
             zone
         );
 
-        let response = self
-            .http_client
-            .post(&url)
-            .bearer_auth(&token)
-            .json(&record)
-            .send()
-            .await
-            .context("Failed to call Cloudflare API")?;
-
-        let cf_response: CloudflareResponse<DnsRecordResult> = response
-            .json()
-            .await
-            .context("Failed to parse Cloudflare response")?;
-
-        if !cf_response.success {
-            let errors: Vec<String> = cf_response
-                .errors
-                .iter()
-                .map(|e| e.message.clone())
-                .collect();
-            anyhow::bail!("Cloudflare error: {}", errors.join(", "));
-        }
-
-        let result = cf_response.result.context("No result in response")?;
+        let request = self.http_client.post(&url).bearer_auth(&token).json(&record);
+        let result: DnsRecordResult = self
+            .call("create_dns_record", request)
+            .await?
+            .context("No result in response")?;
         info!("DNS record created: {} (ID: {})", result.name, result.id);
 
         Ok(result.id)
@@ -291,27 +326,8 @@ impl CloudflareTool {
             zone, record_id
         );
 
-        let response = self
-            .http_client
-            .delete(&url)
-            .bearer_auth(&token)
-            .send()
-            .await
-            .context("Failed to call Cloudflare API")?;
-
-        let cf_response: CloudflareResponse<serde_json::Value> = response
-            .json()
-            .await
-            .context("Failed to parse Cloudflare response")?;
-
-        if !cf_response.success {
-            let errors: Vec<String> = cf_response
-                .errors
-                .iter()
-                .map(|e| e.message.clone())
-                .collect();
-            anyhow::bail!("Cloudflare error: {}", errors.join(", "));
-        }
+        let request = self.http_client.delete(&url).bearer_auth(&token);
+        self.call::<serde_json::Value>("delete_dns_record", request).await?;
 
         info!("DNS record deleted: {}", record_id);
         Ok(())
@@ -331,29 +347,57 @@ impl CloudflareTool {
             zone
         );
 
-        let response = self
-            .http_client
-            .get(&url)
-            .bearer_auth(&token)
-            .send()
-            .await
-            .context("Failed to call Cloudflare API")?;
+        let request = self.http_client.get(&url).bearer_auth(&token);
+        let records = self.call("list_dns_records", request).await?;
 
-        let cf_response: CloudflareResponse<Vec<serde_json::Value>> = response
-            .json()
-            .await
-            .context("Failed to parse Cloudflare response")?;
+        Ok(records.unwrap_or_default())
+    }
 
-        if !cf_response.success {
-            let errors: Vec<String> = cf_response
-                .errors
-                .iter()
-                .map(|e| e.message.clone())
-                .collect();
-            anyhow::bail!("Cloudflare error: {}", errors.join(", "));
+    /// Send a Cloudflare API request and unwrap its response envelope.
+    ///
+    /// Every call is counted in `cloudflare_api_calls_total` by operation and
+    /// outcome (`success`, `rejected` by Cloudflare, or transport `error`).
+    /// Rejections and unreadable responses fail with a [`CloudflareApiError`].
+    async fn call<T: DeserializeOwned>(
+        &self,
+        operation: &'static str,
+        request: RequestBuilder,
+    ) -> Result<Option<T>> {
+        let start = Instant::now();
+        let response = async {
+            let response = request.send().await.context("Failed to call Cloudflare API")?;
+            let status = response.status();
+            let body = response.bytes().await.context("Failed to read Cloudflare response")?;
+            Ok::<_, anyhow::Error>((status, serde_json::from_slice::<CloudflareResponse<T>>(&body)))
         }
+        .await;
+
+        let (outcome, result) = match response {
+            Ok((status, Ok(cf_response))) if cf_response.success && status.is_success() => {
+                ("success", Ok(cf_response.result))
+            }
+            Ok((status, Ok(cf_response))) => (
+                "rejected",
+                Err(CloudflareApiError::from_response(status, &cf_response.errors).into()),
+            ),
+            // No envelope, e.g. an HTML error page from an edge
+            Ok((status, Err(e))) => (
+                "error",
+                Err(CloudflareApiError::Upstream {
+                    status: status.as_u16(),
+                    message: format!("unreadable response: {}", e),
+                }
+                .into()),
+            ),
+            Err(e) => ("error", Err(e)),
+        };
 
-        Ok(cf_response.result.unwrap_or_default())
+        metrics::counter!("cloudflare_api_calls_total", "operation" => operation, "outcome" => outcome)
+            .increment(1);
+        metrics::histogram!("cloudflare_api_call_duration_seconds", "operation" => operation)
+            .record(start.elapsed().as_secs_f64());
+
+        result
     }
 }
 
@@ -372,6 +416,35 @@ fn base64_decode(input: &str) -> Result<Vec<u8>> {
 mod tests {
     use super::*;
 
+    fn errors(codes: &[i32]) -> Vec<CloudflareError> {
+        codes
+            .iter()
+            .map(|&code| CloudflareError {
+                code,
+                message: format!("error {}", code),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classifies_rejections() {
+        let classify = |status: u16, codes: &[i32]| {
+            CloudflareApiError::from_response(StatusCode::from_u16(status).unwrap(), &errors(codes))
+        };
+
+        assert!(matches!(classify(400, &[81057]), CloudflareApiError::Conflict(_)));
+        assert!(matches!(classify(409, &[]), CloudflareApiError::Conflict(_)));
+        assert!(matches!(classify(400, &[9005]), CloudflareApiError::Invalid(_)));
+        assert!(matches!(classify(404, &[7003]), CloudflareApiError::Invalid(_)));
+        assert!(matches!(classify(403, &[10000]), CloudflareApiError::Upstream { status: 403, .. }));
+        assert!(matches!(classify(429, &[]), CloudflareApiError::Upstream { status: 429, .. }));
+        assert!(matches!(classify(502, &[]), CloudflareApiError::Upstream { status: 502, .. }));
+        assert_eq!(
+            classify(400, &[9005]).to_string(),
+            "Cloudflare rejected the request: error 9005 (9005)"
+        );
+    }
+
     #[test]
     fn test_tool_requires_project_id() {
         // Ensure LORNU_GCP_PROJECT is not set for this test
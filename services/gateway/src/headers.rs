@@ -0,0 +1,182 @@
+//! Header Forwarding
+//!
+//! RFC 7230 §6.1 header handling for proxied requests. Hop-by-hop headers,
+//! including any named in `Connection`, apply to a single connection and are
+//! never forwarded; end-to-end headers pass through in both directions.
+
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use std::net::IpAddr;
+
+/// Request id header, generated when the client does not send one.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+const FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Longest client-supplied request id that is passed through.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Headers that only apply to a single connection (RFC 7230 §6.1, plus the
+/// non-standard `keep-alive` and `proxy-connection`).
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Copy `headers`, leaving out hop-by-hop headers and those the `Connection`
+/// header marks as hop-by-hop.
+pub fn end_to_end(headers: &HeaderMap) -> HeaderMap {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|token| HeaderName::try_from(token.trim()).ok())
+        .collect();
+
+    let mut forwarded = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        if HOP_BY_HOP.contains(&name.as_str()) || listed.contains(name) {
+            continue;
+        }
+        forwarded.append(name.clone(), value.clone());
+    }
+    forwarded
+}
+
+/// The client's request id if it sent a usable one, otherwise a new one.
+pub fn request_id(headers: &HeaderMap) -> HeaderValue {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN && v.to_str().is_ok())
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&uuid::Uuid::new_v4().to_string()).expect("uuid is a valid header value")
+        })
+}
+
+/// Headers to send upstream for a client request.
+///
+/// Drops `host` (reqwest sets it from the upstream URL), appends the client
+/// address to `X-Forwarded-For`, keeps an `X-Forwarded-Proto` set by a load
+/// balancer in front of the gateway and sets `X-Request-Id`.
+pub fn upstream_request(incoming: &HeaderMap, client: Option<IpAddr>, request_id: &HeaderValue) -> HeaderMap {
+    let mut headers = end_to_end(incoming);
+    headers.remove(header::HOST);
+
+    if let Some(client) = client {
+        let chain = match headers.get(FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+            Some(prior) => format!("{}, {}", prior, client),
+            None => client.to_string(),
+        };
+        headers.remove(FORWARDED_FOR);
+        if let Ok(value) = HeaderValue::from_str(&chain) {
+            headers.insert(FORWARDED_FOR, value);
+        }
+    }
+
+    if !headers.contains_key(FORWARDED_PROTO) {
+        headers.insert(FORWARDED_PROTO, HeaderValue::from_static("http"));
+    }
+    headers.insert(REQUEST_ID_HEADER, request_id.clone());
+
+    headers
+}
+
+/// Headers to return to the client for an upstream response.
+pub fn downstream_response(upstream: &HeaderMap, request_id: &HeaderValue) -> HeaderMap {
+    let mut headers = end_to_end(upstream);
+    if !headers.contains_key(REQUEST_ID_HEADER) {
+        headers.insert(REQUEST_ID_HEADER, request_id.clone());
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_strips_hop_by_hop_headers() {
+        let incoming = headers(&[
+            ("connection", "keep-alive, x-debug-session"),
+            ("keep-alive", "timeout=5"),
+            ("transfer-encoding", "chunked"),
+            ("upgrade", "h2c"),
+            ("x-debug-session", "abc"),
+            ("content-type", "application/json"),
+            ("authorization", "Bearer token"),
+        ]);
+
+        let forwarded = end_to_end(&incoming);
+
+        assert_eq!(forwarded.len(), 2);
+        assert_eq!(forwarded["content-type"], "application/json");
+        assert_eq!(forwarded["authorization"], "Bearer token");
+    }
+
+    #[test]
+    fn test_upstream_request_headers() {
+        let incoming = headers(&[
+            ("host", "gateway.lornu.ai"),
+            ("x-forwarded-for", "203.0.113.7"),
+            ("x-forwarded-proto", "https"),
+            ("accept", "text/event-stream"),
+        ]);
+        let id = HeaderValue::from_static("req-1");
+
+        let upstream = upstream_request(&incoming, Some("10.0.0.4".parse().unwrap()), &id);
+
+        assert!(upstream.get("host").is_none());
+        assert_eq!(upstream["x-forwarded-for"], "203.0.113.7, 10.0.0.4");
+        assert_eq!(upstream["x-forwarded-proto"], "https");
+        assert_eq!(upstream["x-request-id"], "req-1");
+        assert_eq!(upstream["accept"], "text/event-stream");
+    }
+
+    #[test]
+    fn test_response_keeps_repeated_headers() {
+        let upstream = headers(&[
+            ("set-cookie", "session=1"),
+            ("set-cookie", "theme=dark"),
+            ("cache-control", "no-store"),
+            ("connection", "close"),
+        ]);
+
+        let response = downstream_response(&upstream, &HeaderValue::from_static("req-1"));
+
+        assert_eq!(response.get_all("set-cookie").iter().count(), 2);
+        assert_eq!(response["cache-control"], "no-store");
+        assert_eq!(response["x-request-id"], "req-1");
+        assert!(response.get("connection").is_none());
+    }
+
+    #[test]
+    fn test_request_id_generated_when_missing_or_invalid() {
+        let kept = request_id(&headers(&[("x-request-id", "client-id")]));
+        assert_eq!(kept, "client-id");
+
+        let generated = request_id(&HeaderMap::new());
+        assert!(uuid::Uuid::parse_str(generated.to_str().unwrap()).is_ok());
+
+        let too_long = HeaderValue::from_str(&"x".repeat(MAX_REQUEST_ID_LEN + 1)).unwrap();
+        let mut map = HeaderMap::new();
+        map.insert(REQUEST_ID_HEADER, too_long.clone());
+        assert_ne!(request_id(&map), too_long);
+    }
+}
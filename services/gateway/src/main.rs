@@ -4,26 +4,44 @@
 
 use anyhow::Result;
 use axum::{
-    body::Body,
-    extract::{Path, State},
-    http::{Request, StatusCode},
-    response::{IntoResponse, Response},
-    routing::{any, get},
+    middleware,
+    routing::get,
     Json, Router,
 };
 use reqwest::Client;
-use std::collections::HashMap;
-use std::env;
+use std::future::IntoFuture;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
 use tower_http::cors::CorsLayer;
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
+mod audit;
+mod auth;
+mod headers;
+mod metrics;
+mod proxy;
+mod ratelimit;
+mod routes;
+mod upstream;
+mod websocket;
+
+use proxy::ProxyConfig;
+use routes::Routes;
+
+/// Seconds to let in-flight requests finish after a shutdown signal.
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 25;
+
 #[derive(Clone)]
 struct AppState {
     http_client: Client,
-    routes: Arc<HashMap<String, String>>,
+    routes: Routes,
+    auth: Arc<auth::Authenticator>,
+    limiter: Arc<ratelimit::RateLimiter>,
+    upstreams: Arc<upstream::Upstreams>,
+    proxy: ProxyConfig,
 }
 
 #[tokio::main]
@@ -35,28 +53,37 @@ async fn main() -> Result<()> {
 
     info!("Starting Lornu AI Gateway");
 
+    // No overall request timeout: streamed responses (SSE, chunked) may stay
+    // open indefinitely. The proxy bounds the wait for response headers instead.
+    let proxy = ProxyConfig::from_env();
     let http_client = Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
+        .connect_timeout(Duration::from_secs(5))
         .build()?;
 
-    let mut routes = HashMap::new();
-    routes.insert(
-        "engine".to_string(),
-        env::var("ENGINE_URL").unwrap_or_else(|_| "http://engine:8080".to_string()),
-    );
-    routes.insert(
-        "worker".to_string(),
-        env::var("WORKER_URL").unwrap_or_else(|_| "http://agent-worker:8082".to_string()),
-    );
+    let authenticator = Arc::new(auth::Authenticator::from_env()?);
+    let auditor = audit::Auditor::from_env()?;
+    let routes = Routes::from_env()?;
+    let upstreams = Arc::new(upstream::Upstreams::default());
+    tokio::spawn(upstreams.clone().run_health_checks(routes.clone(), http_client.clone()));
 
     let state = AppState {
         http_client,
-        routes: Arc::new(routes),
+        routes,
+        upstreams,
+        auth: authenticator.clone(),
+        limiter: Arc::new(ratelimit::RateLimiter::default()),
+        proxy,
     };
 
+    let prometheus = metrics::install()?;
+
     let app = Router::new()
         .route("/health", get(health_check))
-        .route("/api/v1/*path", any(proxy_request))
+        .route("/metrics", get(move || std::future::ready(prometheus.render())))
+        .fallback(proxy::proxy_request)
+        .layer(middleware::from_fn_with_state(authenticator, auth::authenticate))
+        .layer(middleware::from_fn_with_state(auditor, audit::record))
+        .layer(middleware::from_fn(metrics::track_requests))
         .layer(CorsLayer::permissive())
         .with_state(state);
 
@@ -64,79 +91,74 @@ async fn main() -> Result<()> {
     info!("Gateway listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let (signal_tx, signal_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = signal_tx.send(true);
+    });
+    let server = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(signalled(signal_rx.clone()))
+        .into_future();
+
+    // Long-lived connections (streams, WebSockets) must not hold up shutdown
+    let grace = shutdown_grace();
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            signalled(signal_rx).await;
+            tokio::time::sleep(grace).await;
+        } => warn!("Grace period of {}s expired; closing open connections", grace.as_secs()),
+    }
 
+    info!("Shutdown complete");
     Ok(())
 }
 
-async fn health_check() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "service": "lornu-gateway",
-        "version": env!("CARGO_PKG_VERSION")
-    }))
-}
-
-async fn proxy_request(
-    State(state): State<AppState>,
-    Path(path): Path<String>,
-    request: Request<Body>,
-) -> Response {
-    let parts: Vec<&str> = path.splitn(2, '/').collect();
-    let service = parts.first().unwrap_or(&"");
-    let remaining = parts.get(1).unwrap_or(&"");
-
-    let target_url = match state.routes.get(*service) {
-        Some(url) => format!("{}/{}", url, remaining),
-        None => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(serde_json::json!({"error": "service_not_found"})),
-            )
-                .into_response()
-        }
+/// Resolves on SIGTERM or Ctrl+C.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
     };
 
-    info!("Proxying to: {}", target_url);
-
-    let method = request.method().clone();
-    let headers = request.headers().clone();
-    let mut req_builder = state.http_client.request(method, &target_url);
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    for (name, value) in headers.iter() {
-        if name != "host" {
-            req_builder = req_builder.header(name.clone(), value.clone());
-        }
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
 
-    let body_bytes = match axum::body::to_bytes(request.into_body(), usize::MAX).await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": e.to_string()})),
-            )
-                .into_response()
-        }
-    };
+    info!("Shutdown signal received, finishing in-flight requests");
+}
 
-    if !body_bytes.is_empty() {
-        req_builder = req_builder.body(body_bytes.to_vec());
-    }
+/// Seconds to let in-flight requests finish after a shutdown signal, from
+/// `SHUTDOWN_GRACE_SECS`.
+fn shutdown_grace() -> Duration {
+    let secs = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_SECS);
+    Duration::from_secs(secs)
+}
 
-    match req_builder.send().await {
-        Ok(resp) => {
-            let status = resp.status();
-            let body = resp.bytes().await.unwrap_or_default();
-            Response::builder()
-                .status(status)
-                .body(Body::from(body))
-                .unwrap_or_else(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Error").into_response())
-        }
-        Err(e) => (
-            StatusCode::BAD_GATEWAY,
-            Json(serde_json::json!({"error": e.to_string()})),
-        )
-            .into_response(),
-    }
+/// Resolves once the shutdown signal has been received.
+async fn signalled(mut signal: watch::Receiver<bool>) {
+    let _ = signal.wait_for(|received| *received).await;
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "healthy",
+        "service": "lornu-gateway",
+        "version": env!("CARGO_PKG_VERSION")
+    }))
 }
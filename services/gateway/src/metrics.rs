@@ -0,0 +1,48 @@
+//! Metrics
+//!
+//! Prometheus metrics for the gateway, exposed at `/metrics`: requests per
+//! matched route, plus per-upstream outcomes and latency of proxied calls.
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::time::Duration;
+
+pub use lornu_http_metrics::{track_requests, RouteLabel};
+
+/// Install the global Prometheus recorder.
+pub fn install() -> Result<PrometheusHandle> {
+    lornu_http_metrics::install(&[])
+}
+
+/// Record the outcome of a proxied call; `status` is `None` when the upstream
+/// could not be reached.
+pub fn record_upstream(service: &str, status: Option<StatusCode>, elapsed: Duration) {
+    let status = status.map_or_else(|| "error".to_string(), |s| s.as_u16().to_string());
+    metrics::counter!("gateway_upstream_requests_total", "service" => service.to_string(), "status" => status)
+        .increment(1);
+    metrics::histogram!("gateway_upstream_duration_seconds", "service" => service.to_string())
+        .record(elapsed.as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+
+    fn handle() -> &'static PrometheusHandle {
+        static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+        HANDLE.get_or_init(|| install().unwrap())
+    }
+
+    #[test]
+    fn test_upstream_outcomes() {
+        let handle = handle();
+        record_upstream("worker", Some(StatusCode::OK), Duration::from_millis(20));
+        record_upstream("worker", None, Duration::from_millis(5));
+
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"gateway_upstream_requests_total{service="worker",status="200"} 1"#));
+        assert!(rendered.contains(r#"gateway_upstream_requests_total{service="worker",status="error"} 1"#));
+    }
+}
@@ -0,0 +1,811 @@
+//! Request Proxying
+//!
+//! Forwards requests to the upstream of the matching route. Bodies are
+//! streamed in both directions, so large uploads, chunked responses and
+//! server-sent events pass through without being buffered. WebSocket
+//! upgrades are handed to [`websocket`].
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::{StreamExt, TryStreamExt};
+use http_body_util::{LengthLimitError, Limited};
+use hyper::body::Body as _;
+use std::error::Error as StdError;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::time::error::Elapsed;
+use tracing::{info, warn};
+
+use crate::auth::Identity;
+use crate::routes::{self, Route};
+use crate::{headers, metrics, upstream, websocket, AppState};
+
+/// Default cap on request bodies (`GATEWAY_MAX_BODY_BYTES`).
+pub const DEFAULT_MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+/// Default time to wait for upstream response headers, and for each chunk of
+/// a request body (`GATEWAY_UPSTREAM_TIMEOUT_SECS`).
+pub const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Proxy settings
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// Largest request body forwarded upstream
+    pub max_body_bytes: usize,
+    /// How long to wait for the upstream's response headers once the request
+    /// body has been sent, and the longest pause allowed while the client
+    /// uploads it; once headers arrive the response body may stream for as
+    /// long as the upstream keeps it open
+    pub upstream_timeout: Duration,
+    /// How long a WebSocket tunnel may go without traffic before it is closed
+    pub ws_idle_timeout: Duration,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            upstream_timeout: DEFAULT_UPSTREAM_TIMEOUT,
+            ws_idle_timeout: websocket::DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Read `GATEWAY_MAX_BODY_BYTES`, `GATEWAY_UPSTREAM_TIMEOUT_SECS` and
+    /// `GATEWAY_WS_IDLE_TIMEOUT_SECS`.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_body_bytes: std::env::var("GATEWAY_MAX_BODY_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(defaults.max_body_bytes),
+            upstream_timeout: std::env::var("GATEWAY_UPSTREAM_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.upstream_timeout),
+            ws_idle_timeout: std::env::var("GATEWAY_WS_IDLE_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.ws_idle_timeout),
+        }
+    }
+}
+
+/// Fallback handler: proxy any request matching a route in the route table.
+pub async fn proxy_request(State(state): State<AppState>, request: Request<Body>) -> Response {
+    // Routes match the path as sent; one the upstream URL would normalize
+    // differently could reach another route's upstream
+    if routes::has_dot_segments(request.uri().path()) {
+        warn!("Rejected {} {}: dot segments in path", request.method(), request.uri().path());
+        return error_response(StatusCode::BAD_REQUEST, "invalid_path");
+    }
+
+    let table = state.routes.current();
+    let Some(route) = table.resolve(request.uri().path()) else {
+        return error_response(StatusCode::NOT_FOUND, "service_not_found");
+    };
+
+    let mut response = forward(&state, route, request).await;
+    response
+        .extensions_mut()
+        .insert(metrics::RouteLabel(route.name.clone()));
+    response
+}
+
+async fn forward(state: &AppState, route: &Route, request: Request<Body>) -> Response {
+    if !route.allows(request.method()) {
+        let mut response = error_response(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed");
+        if let Ok(allow) = HeaderValue::from_str(&route.methods.join(", ").to_uppercase()) {
+            response.headers_mut().insert(header::ALLOW, allow);
+        }
+        return response;
+    }
+
+    let identity = request.extensions().get::<Identity>();
+    if let Err(e) = state.auth.authorize(identity, &route.scopes) {
+        warn!("Rejected {} {}: {}", request.method(), request.uri().path(), e);
+        return e.into_response();
+    }
+
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let rate_limit_headers = match state.limiter.check(route, identity, client).await {
+        Ok(headers) => headers,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let max_body_bytes = state.proxy.max_body_bytes;
+    let declared_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_length.is_some_and(|len| len > max_body_bytes) {
+        return error_response(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large");
+    }
+
+    let request_path = request.uri().clone();
+    let upstream_timeout = route
+        .timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(state.proxy.upstream_timeout);
+
+    let method = request.method().clone();
+    let request_id = headers::request_id(request.headers());
+    let mut upstream_headers = headers::upstream_request(request.headers(), client, &request_id);
+
+    if websocket::is_upgrade(request.headers()) {
+        let Some(selected) = state.upstreams.select(route) else {
+            warn!("No healthy upstream for {}", route.name);
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, "upstream_unavailable");
+        };
+        let target_url = route.target_url(selected.url(), request_path.path(), request_path.query());
+        info!("Tunnelling WebSocket to: {}", target_url);
+
+        websocket::upgrade_headers(&mut upstream_headers);
+        return websocket::tunnel(
+            &state.http_client,
+            &target_url,
+            request,
+            upstream_headers,
+            selected,
+            upstream_timeout,
+            state.proxy.ws_idle_timeout,
+        )
+        .await;
+    }
+
+    // A streamed body cannot be replayed, so only bodiless idempotent requests are retried
+    let retryable = is_idempotent(&method) && request.body().size_hint().exact() == Some(0);
+    let max_retries = if retryable { route.retry.attempts } else { 0 };
+
+    // Stream the body upstream, failing it once it grows past the limit
+    let mut body = (!retryable).then(|| Body::new(Limited::new(request.into_body(), max_body_bytes)));
+
+    let mut retry = 0;
+    let (result, selected) = loop {
+        // Fail fast rather than wait on targets known to be down
+        let Some(mut selected) = state.upstreams.select(route) else {
+            warn!("No healthy upstream for {}", route.name);
+            return error_response(StatusCode::SERVICE_UNAVAILABLE, "upstream_unavailable");
+        };
+
+        let target_url = route.target_url(selected.url(), request_path.path(), request_path.query());
+        info!("Proxying to: {}", target_url);
+
+        let mut req_builder = state
+            .http_client
+            .request(method.clone(), &target_url)
+            .headers(upstream_headers.clone());
+        let mut uploaded = None;
+        if let Some(body) = body.take() {
+            let (stream, sent) = upload(body, upstream_timeout);
+            req_builder = req_builder.body(stream);
+            uploaded = Some(sent);
+        }
+
+        let start = Instant::now();
+        let result = send(req_builder, uploaded, upstream_timeout).await;
+        let status = match &result {
+            Ok(Ok(resp)) => Some(resp.status()),
+            _ => None,
+        };
+        metrics::record_upstream(&route.name, status, start.elapsed());
+
+        selected.report(match &result {
+            Ok(Ok(resp)) => !upstream::is_failure_status(resp.status()),
+            // An oversized or stalled body is the client's fault, not the upstream's
+            Ok(Err(e)) => is_body_too_large(e) || has_source::<UploadStalled>(e),
+            Err(_) => false,
+        });
+
+        let should_retry = match &result {
+            Ok(Ok(resp)) => route.retry.retries_status(resp.status()),
+            Ok(Err(e)) => e.is_connect(),
+            Err(_) => true,
+        };
+        if !should_retry || retry >= max_retries {
+            break (result, selected);
+        }
+
+        retry += 1;
+        let backoff = route.retry.backoff(retry);
+        warn!(
+            "Retrying {} ({}/{}) in {}ms",
+            route.name,
+            retry,
+            max_retries,
+            backoff.as_millis()
+        );
+        tokio::time::sleep(backoff).await;
+    };
+
+    let upstream = match result {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(e)) if is_body_too_large(&e) => {
+            return error_response(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large")
+        }
+        Ok(Err(e)) if has_source::<UploadStalled>(&e) => {
+            warn!("Request body for {} stalled for {}s", route.name, upstream_timeout.as_secs());
+            return error_response(StatusCode::REQUEST_TIMEOUT, "request_body_timeout");
+        }
+        Ok(Err(e)) => {
+            warn!("Upstream {} failed: {}", route.name, e);
+            return error_response(StatusCode::BAD_GATEWAY, &e.to_string());
+        }
+        Err(_) => {
+            warn!(
+                "Upstream {} sent no response within {}s",
+                route.name,
+                upstream_timeout.as_secs()
+            );
+            return error_response(StatusCode::GATEWAY_TIMEOUT, "upstream_timeout");
+        }
+    };
+
+    let status = upstream.status();
+    let mut response_headers = headers::downstream_response(upstream.headers(), &request_id);
+    response_headers.extend(rate_limit_headers);
+    if is_event_stream(response_headers.get(header::CONTENT_TYPE)) {
+        // Ask intermediaries (e.g. nginx) not to buffer server-sent events
+        response_headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+    }
+
+    // The target counts as busy until the body has been streamed
+    let body = upstream.bytes_stream().map_err(move |e| {
+        warn!("Upstream body from {} interrupted: {}", selected.url(), e);
+        e
+    });
+
+    let mut response = Response::new(Body::from_stream(body));
+    *response.status_mut() = status;
+    *response.headers_mut() = response_headers;
+    response
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+pub(crate) fn error_response(status: StatusCode, error: &str) -> Response {
+    (status, Json(serde_json::json!({"error": error}))).into_response()
+}
+
+fn is_event_stream(content_type: Option<&HeaderValue>) -> bool {
+    content_type
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"))
+}
+
+/// Whether sending failed because the request body exceeded the size limit.
+fn is_body_too_large(error: &reqwest::Error) -> bool {
+    has_source::<LengthLimitError>(error)
+}
+
+/// Whether `error` was caused by an error of type `E`.
+fn has_source<E: StdError + 'static>(error: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn StdError + 'static)> = error.source();
+    while let Some(e) = source {
+        if e.is::<E>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// The client sent no part of the request body for the upstream timeout
+#[derive(Debug)]
+struct UploadStalled;
+
+impl std::fmt::Display for UploadStalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("request body stalled")
+    }
+}
+
+impl StdError for UploadStalled {}
+
+/// Stream `body` upstream, failing it if the client sends nothing for
+/// `idle`. The receiver resolves once the body has been sent in full.
+fn upload(body: Body, idle: Duration) -> (reqwest::Body, oneshot::Receiver<()>) {
+    type BoxError = Box<dyn StdError + Send + Sync>;
+
+    let (sent_tx, sent_rx) = oneshot::channel();
+    let stream = futures::stream::unfold(
+        (body.into_data_stream(), Some(sent_tx)),
+        move |(mut data, mut sent)| async move {
+            match tokio::time::timeout(idle, data.next()).await {
+                Ok(Some(chunk)) => Some((chunk.map_err(BoxError::from), (data, sent))),
+                Ok(None) => {
+                    if let Some(sent) = sent.take() {
+                        let _ = sent.send(());
+                    }
+                    None
+                }
+                Err(_) => Some((Err(BoxError::from(UploadStalled)), (data, None))),
+            }
+        },
+    );
+    (reqwest::Body::wrap_stream(stream), sent_rx)
+}
+
+/// Send `request`, allowing `timeout` for the response headers once the body
+/// (if `uploaded` is given) has been sent: a long upload is not a slow upstream.
+async fn send(
+    request: reqwest::RequestBuilder,
+    uploaded: Option<oneshot::Receiver<()>>,
+    timeout: Duration,
+) -> Result<reqwest::Result<reqwest::Response>, Elapsed> {
+    let response = request.send();
+    tokio::pin!(response);
+    if let Some(uploaded) = uploaded {
+        tokio::select! {
+            // The upstream may answer (or fail) before reading the whole body
+            result = &mut response => return Ok(result),
+            _ = uploaded => {}
+        }
+    }
+    tokio::time::timeout(timeout, response).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Authenticator;
+    use crate::ratelimit::{RateLimitPolicy, RateLimiter};
+    use crate::routes::{RetryPolicy, RouteTable, Routes};
+    use crate::upstream::{CircuitBreakerPolicy, Upstreams};
+    use axum::{
+        response::sse::{Event, Sse},
+        routing::{get, post},
+        Router,
+    };
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// Serve `app` on an ephemeral port and return its base URL.
+    async fn spawn_upstream(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn worker_route(upstream: String) -> Route {
+        Route {
+            name: "worker".to_string(),
+            prefix: "/api/v1/worker".to_string(),
+            upstream: None,
+            upstreams: vec![upstream],
+            balance: Default::default(),
+            health_check: None,
+            circuit_breaker: Default::default(),
+            rewrite: String::new(),
+            methods: Vec::new(),
+            timeout_secs: None,
+            retry: RetryPolicy::default(),
+            scopes: Vec::new(),
+            rate_limit: None,
+            daily_quota: None,
+        }
+    }
+
+    fn gateway_with(route: Route, proxy: ProxyConfig, auth: Authenticator) -> Router {
+        let state = AppState {
+            http_client: reqwest::Client::new(),
+            routes: Routes::new(RouteTable::new(vec![route]).unwrap()),
+            auth: Arc::new(auth),
+            limiter: Arc::new(RateLimiter::default()),
+            upstreams: Arc::new(Upstreams::default()),
+            proxy,
+        };
+        Router::new().fallback(proxy_request).with_state(state)
+    }
+
+    fn gateway(upstream: String, proxy: ProxyConfig) -> Router {
+        gateway_with(worker_route(upstream), proxy, Authenticator::disabled())
+    }
+
+    fn request(method: &str, uri: &str, body: Body) -> Request<Body> {
+        Request::builder().method(method).uri(uri).body(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_streams_server_sent_events() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/events",
+            get(|| async {
+                let events = futures::stream::iter(
+                    ["a", "b", "c"].map(|token| Ok::<_, Infallible>(Event::default().data(token))),
+                );
+                Sse::new(events)
+            }),
+        ))
+        .await;
+
+        let response = gateway(upstream, ProxyConfig::default())
+            .oneshot(request("GET", "/api/v1/worker/events", Body::empty()))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "data: a\n\ndata: b\n\ndata: c\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_streams_request_body() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/echo",
+            post(|body: axum::body::Bytes| async move { body.len().to_string() }),
+        ))
+        .await;
+
+        let chunks = futures::stream::iter(
+            (0..64).map(|_| Ok::<_, Infallible>(axum::body::Bytes::from(vec![b'x'; 1024]))),
+        );
+        let response = gateway(upstream, ProxyConfig::default())
+            .oneshot(request("POST", "/api/v1/worker/echo", Body::from_stream(chunks)))
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "65536");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_bodies() {
+        let upstream = spawn_upstream(Router::new().route("/echo", post(|| async { "ok" }))).await;
+        let proxy = ProxyConfig {
+            max_body_bytes: 16,
+            ..ProxyConfig::default()
+        };
+        let app = gateway(upstream, proxy);
+
+        // Declared up front
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/api/v1/worker/echo", Body::from(vec![0u8; 32])))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Discovered while streaming
+        let chunks = futures::stream::iter(
+            (0..4).map(|_| Ok::<_, Infallible>(axum::body::Bytes::from(vec![0u8; 8]))),
+        );
+        let response = app
+            .oneshot(request("POST", "/api/v1/worker/echo", Body::from_stream(chunks)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_times_out_waiting_for_headers() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "late"
+            }),
+        ))
+        .await;
+        let proxy = ProxyConfig {
+            upstream_timeout: Duration::from_millis(100),
+            ..ProxyConfig::default()
+        };
+
+        let response = gateway(upstream, proxy)
+            .oneshot(request("GET", "/api/v1/worker/slow", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_slow_uploads_are_not_timed_out() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/echo",
+            post(|body: axum::body::Bytes| async move { body.len().to_string() }),
+        ))
+        .await;
+        let proxy = ProxyConfig {
+            upstream_timeout: Duration::from_millis(300),
+            ..ProxyConfig::default()
+        };
+        let app = gateway(upstream, proxy);
+        let trickle = |chunks: usize, pause: Duration| {
+            Body::from_stream(futures::stream::iter(0..chunks).then(move |_| async move {
+                tokio::time::sleep(pause).await;
+                Ok::<_, Infallible>(axum::body::Bytes::from(vec![b'x'; 1024]))
+            }))
+        };
+
+        // Longer than the timeout overall, but never idle for that long
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/api/v1/worker/echo", trickle(8, Duration::from_millis(100))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "8192");
+
+        let response = app
+            .oneshot(request("POST", "/api/v1/worker/echo", trickle(2, Duration::from_secs(1))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_forwards_query_and_headers() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/tasks",
+            get(|request: Request<Body>| async move {
+                let seen = serde_json::json!({
+                    "query": request.uri().query(),
+                    "forwarded_for": request.headers().get("x-forwarded-for").and_then(|v| v.to_str().ok()),
+                    "request_id": request.headers().get("x-request-id").and_then(|v| v.to_str().ok()),
+                    "debug": request.headers().get("x-debug").is_some(),
+                });
+                (
+                    [
+                        (header::SET_COOKIE, "session=1"),
+                        (header::CACHE_CONTROL, "no-store"),
+                    ],
+                    Json(seen),
+                )
+            }),
+        ))
+        .await;
+
+        let mut req = Request::builder()
+            .uri("/api/v1/worker/tasks?status=running&limit=5")
+            .header("connection", "x-debug")
+            .header("x-debug", "1")
+            .header("x-request-id", "req-42")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 4], 51000))));
+
+        let response = gateway(upstream, ProxyConfig::default()).oneshot(req).await.unwrap();
+
+        assert_eq!(response.headers()[header::SET_COOKIE], "session=1");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        assert_eq!(response.headers()["x-request-id"], "req-42");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let seen: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(seen["query"], "status=running&limit=5");
+        assert_eq!(seen["forwarded_for"], "10.0.0.4");
+        assert_eq!(seen["request_id"], "req-42");
+        assert_eq!(seen["debug"], false);
+    }
+
+    #[tokio::test]
+    async fn test_retries_idempotent_requests() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let upstream = spawn_upstream(Router::new().route(
+            "/flaky",
+            get(move || async move {
+                match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::OK,
+                }
+            })
+            .post(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+        ))
+        .await;
+
+        let mut route = worker_route(upstream);
+        route.retry = RetryPolicy {
+            attempts: 2,
+            backoff_ms: 1,
+            ..RetryPolicy::default()
+        };
+        let app = gateway_with(route, ProxyConfig::default(), Authenticator::disabled());
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/v1/worker/flaky", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Requests with a body are sent once
+        let response = app
+            .oneshot(request("POST", "/api/v1/worker/flaky", Body::from("{}")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_headers_and_429() {
+        let upstream = spawn_upstream(Router::new().route("/tasks", get(|| async { "ok" }))).await;
+        let mut route = worker_route(upstream);
+        route.rate_limit = Some(serde_yaml::from_str::<RateLimitPolicy>("{ rate: 2, key: ip }").unwrap());
+        let app = gateway_with(route, ProxyConfig::default(), Authenticator::disabled());
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ratelimit-remaining"], "1");
+
+        app.clone()
+            .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+            .await
+            .unwrap();
+        let response = app
+            .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_and_fails_fast() {
+        let healthy = spawn_upstream(Router::new().route("/tasks", get(|| async { "ok" }))).await;
+        let mut route = worker_route("http://127.0.0.1:9".to_string());
+        route.upstreams.push(healthy);
+        route.circuit_breaker = CircuitBreakerPolicy {
+            failures: 1,
+            open_secs: 60,
+        };
+        let app = gateway_with(route, ProxyConfig::default(), Authenticator::disabled());
+
+        // The dead target is tried once, then ejected
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        for _ in 0..3 {
+            let response = app
+                .clone()
+                .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let mut dead = worker_route("http://127.0.0.1:9".to_string());
+        dead.circuit_breaker.failures = 1;
+        let app = gateway_with(dead, ProxyConfig::default(), Authenticator::disabled());
+        app.clone()
+            .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+            .await
+            .unwrap();
+        let response = app
+            .oneshot(request("GET", "/api/v1/worker/tasks", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_tunnels_websockets() {
+        use axum::extract::ws::{Message, WebSocketUpgrade};
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite;
+
+        let upstream = spawn_upstream(Router::new().route(
+            "/session",
+            get(|ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(Message::Text(text))) = socket.recv().await {
+                        if socket.send(Message::Text(format!("echo: {}", text))).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }),
+        ))
+        .await;
+        let gateway = spawn_upstream(gateway(upstream, ProxyConfig::default())).await;
+
+        let url = format!("{}/api/v1/worker/session", gateway.replace("http://", "ws://"));
+        let (mut socket, response) = tokio_tungstenite::connect_async(url).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        for text in ["hello", "again"] {
+            socket.send(tungstenite::Message::Text(text.to_string())).await.unwrap();
+            let reply = socket.next().await.unwrap().unwrap();
+            assert_eq!(reply.into_text().unwrap(), format!("echo: {}", text));
+        }
+        socket.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_enforces_route_methods() {
+        let mut route = worker_route("http://127.0.0.1:9".to_string());
+        route.methods = vec!["get".to_string(), "head".to_string()];
+
+        let response = gateway_with(route, ProxyConfig::default(), Authenticator::disabled())
+            .oneshot(request("DELETE", "/api/v1/worker/tasks/1", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET, HEAD");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_traversal_to_another_route() {
+        let engine = spawn_upstream(Router::new().fallback(|| async { "engine" })).await;
+        let dns = spawn_upstream(Router::new().fallback(|| async { "dns" })).await;
+        let mut broad = worker_route(engine);
+        broad.prefix = "/api/v1/engine".to_string();
+        let mut narrow = worker_route(dns);
+        narrow.name = "engine-dns".to_string();
+        narrow.prefix = "/api/v1/engine/api/dns".to_string();
+        narrow.scopes = vec!["dns:write".to_string()];
+        let state = AppState {
+            http_client: reqwest::Client::new(),
+            routes: Routes::new(RouteTable::new(vec![broad, narrow]).unwrap()),
+            auth: Arc::new(Authenticator::disabled()),
+            limiter: Arc::new(RateLimiter::default()),
+            upstreams: Arc::new(Upstreams::default()),
+            proxy: ProxyConfig::default(),
+        };
+        let app = Router::new().fallback(proxy_request).with_state(state);
+
+        for path in [
+            "/api/v1/engine/x/../api/dns/create",
+            "/api/v1/engine/x/%2e%2e/api/dns/create",
+            "/api/v1/engine/x/%2E./api/dns/create",
+        ] {
+            let response = app
+                .clone()
+                .oneshot(request("POST", path, Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", path);
+        }
+
+        let response = app
+            .oneshot(request("POST", "/api/v1/engine/api/agents", Body::empty()))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "engine");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_service() {
+        let response = gateway("http://127.0.0.1:9".to_string(), ProxyConfig::default())
+            .oneshot(request("GET", "/api/v1/billing/invoices", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
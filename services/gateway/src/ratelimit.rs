@@ -0,0 +1,509 @@
+//! Rate Limiting and Quotas
+//!
+//! Per-route token buckets keyed by caller, plus daily request quotas per
+//! tenant. Buckets live in memory on each gateway replica; quota counters go
+//! through a [`QuotaStore`] so they can be shared between replicas.
+//!
+//! Allowed responses carry `RateLimit-Limit`, `RateLimit-Remaining`,
+//! `RateLimit-Reset` and `RateLimit-Policy` (IETF httpapi-ratelimit-headers);
+//! rejected ones are `429 Too Many Requests` with `Retry-After`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use lru::LruCache;
+use axum::{
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::auth::Identity;
+use crate::routes::Route;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// Buckets kept per replica; past this the least recently used is dropped.
+const MAX_BUCKETS: usize = 100_000;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// What callers are told apart by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKey {
+    /// Token subject, falling back to the client IP for anonymous requests
+    #[default]
+    Subject,
+    /// Verified API key, falling back to the token subject, then the client IP
+    ApiKey,
+    /// Client IP
+    Ip,
+}
+
+/// Token-bucket settings for a route
+///
+/// ```yaml
+/// rate_limit: { rate: 60, per_secs: 60, burst: 10, key: subject }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    /// Requests allowed per window
+    pub rate: u32,
+    /// Window length in seconds
+    #[serde(default = "default_per_secs")]
+    pub per_secs: u64,
+    /// Bucket size, i.e. how many requests may arrive at once (default: `rate`)
+    #[serde(default)]
+    pub burst: Option<u32>,
+    #[serde(default)]
+    pub key: LimitKey,
+}
+
+fn default_per_secs() -> u64 {
+    60
+}
+
+impl RateLimitPolicy {
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.unwrap_or(self.rate).max(1))
+    }
+
+    /// Tokens added per second
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.rate) / self.per_secs.max(1) as f64
+    }
+
+    fn policy_header(&self) -> String {
+        format!(
+            "{};w={};burst={}",
+            self.rate,
+            self.per_secs,
+            self.burst.unwrap_or(self.rate)
+        )
+    }
+}
+
+/// A caller's tokens, with the limits of the policy it was created for
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+}
+
+/// Result of taking a token
+#[derive(Debug, PartialEq)]
+enum Take {
+    /// Allowed; requests left and seconds until the bucket is full again
+    Allowed { remaining: u64, reset_secs: u64 },
+    /// Limited; seconds until a token is available
+    Limited { retry_after_secs: u64 },
+}
+
+impl Bucket {
+    fn full(policy: &RateLimitPolicy, now: Instant) -> Self {
+        Self {
+            tokens: policy.capacity(),
+            updated: now,
+            capacity: policy.capacity(),
+            refill_rate: policy.refill_rate(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    fn take(&mut self, now: Instant) -> Take {
+        self.refill(now);
+        let rate = self.refill_rate;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Take::Allowed {
+                remaining: self.tokens.floor() as u64,
+                reset_secs: ((self.capacity - self.tokens) / rate).ceil() as u64,
+            }
+        } else {
+            Take::Limited {
+                retry_after_secs: ((1.0 - self.tokens) / rate).ceil().max(1.0) as u64,
+            }
+        }
+    }
+
+    /// Whether the bucket was created for `policy`'s limits.
+    fn has_policy(&self, policy: &RateLimitPolicy) -> bool {
+        self.capacity == policy.capacity() && self.refill_rate == policy.refill_rate()
+    }
+}
+
+/// Storage for daily quota counters
+#[async_trait]
+pub trait QuotaStore: Send + Sync {
+    /// Count one request against `key` for `day` (days since the Unix epoch)
+    /// and return the day's total, including this request.
+    async fn increment(&self, key: &str, day: u64) -> Result<u64>;
+}
+
+/// Per-process quota counters; the default store
+#[derive(Default)]
+pub struct InMemoryQuotaStore {
+    counters: Mutex<DayCounters>,
+}
+
+/// Counters for the current day
+#[derive(Default)]
+struct DayCounters {
+    day: u64,
+    counts: HashMap<String, u64>,
+}
+
+#[async_trait]
+impl QuotaStore for InMemoryQuotaStore {
+    async fn increment(&self, key: &str, day: u64) -> Result<u64> {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        // Yesterday's counters are dropped once the day rolls over; a request
+        // that read the clock just before midnight counts towards the new day
+        if day > counters.day {
+            counters.day = day;
+            counters.counts.clear();
+        }
+
+        let count = counters.counts.entry(key.to_string()).or_insert(0);
+        *count += 1;
+        Ok(*count)
+    }
+}
+
+/// Why a request was rejected
+#[derive(Debug)]
+pub enum Rejection {
+    RateLimited { policy: String, retry_after_secs: u64 },
+    QuotaExceeded { limit: u64, retry_after_secs: u64 },
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let (retry_after, mut response) = match self {
+            Rejection::RateLimited {
+                policy,
+                retry_after_secs,
+            } => {
+                let mut response = (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(serde_json::json!({"error": "rate_limited"})),
+                )
+                    .into_response();
+                if let Ok(value) = HeaderValue::from_str(&policy) {
+                    response.headers_mut().insert(RATELIMIT_POLICY, value);
+                }
+                response
+                    .headers_mut()
+                    .insert(RATELIMIT_REMAINING, HeaderValue::from(0u64));
+                response
+                    .headers_mut()
+                    .insert(RATELIMIT_RESET, HeaderValue::from(retry_after_secs));
+                (retry_after_secs, response)
+            }
+            Rejection::QuotaExceeded {
+                limit,
+                retry_after_secs,
+            } => (
+                retry_after_secs,
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(serde_json::json!({"error": "quota_exceeded", "daily_limit": limit})),
+                )
+                    .into_response(),
+            ),
+        };
+
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        response
+    }
+}
+
+/// Applies route rate limits and tenant quotas
+pub struct RateLimiter {
+    buckets: Mutex<LruCache<String, Bucket>>,
+    quotas: Arc<dyn QuotaStore>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(Arc::new(InMemoryQuotaStore::default()))
+    }
+}
+
+impl RateLimiter {
+    pub fn new(quotas: Arc<dyn QuotaStore>) -> Self {
+        Self::with_max_buckets(quotas, MAX_BUCKETS)
+    }
+
+    fn with_max_buckets(quotas: Arc<dyn QuotaStore>, max_buckets: usize) -> Self {
+        Self {
+            buckets: Mutex::new(LruCache::new(NonZeroUsize::new(max_buckets).unwrap_or(NonZeroUsize::MIN))),
+            quotas,
+        }
+    }
+
+    /// Check a request against `route`'s rate limit and daily quota.
+    ///
+    /// Returns the `RateLimit-*` headers to add to the response, or the
+    /// rejection to send instead of forwarding it.
+    pub async fn check(
+        &self,
+        route: &Route,
+        identity: Option<&Identity>,
+        client: Option<IpAddr>,
+    ) -> Result<HeaderMap, Rejection> {
+        let mut response_headers = HeaderMap::new();
+
+        if let Some(policy) = &route.rate_limit {
+            let key = format!("{}:{}", route.name, caller_key(policy.key, identity, client));
+            match self.take(&key, policy, Instant::now()) {
+                Take::Allowed {
+                    remaining,
+                    reset_secs,
+                } => {
+                    response_headers.insert(RATELIMIT_LIMIT, HeaderValue::from(policy.capacity() as u64));
+                    response_headers.insert(RATELIMIT_REMAINING, HeaderValue::from(remaining));
+                    response_headers.insert(RATELIMIT_RESET, HeaderValue::from(reset_secs));
+                    if let Ok(value) = HeaderValue::from_str(&policy.policy_header()) {
+                        response_headers.insert(RATELIMIT_POLICY, value);
+                    }
+                }
+                Take::Limited { retry_after_secs } => {
+                    warn!("Rate limited {}", key);
+                    return Err(Rejection::RateLimited {
+                        policy: policy.policy_header(),
+                        retry_after_secs,
+                    });
+                }
+            }
+        }
+
+        if let Some(limit) = route.daily_quota {
+            let tenant = identity
+                .and_then(|i| i.tenant.clone())
+                .unwrap_or_else(|| caller_key(LimitKey::Subject, identity, client));
+            let key = format!("{}:{}", route.name, tenant);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+            // A quota backend outage should not take the API down with it
+            match self.quotas.increment(&key, now / SECS_PER_DAY).await {
+                Ok(used) if used > limit => {
+                    warn!("Daily quota of {} exhausted for {}", limit, key);
+                    return Err(Rejection::QuotaExceeded {
+                        limit,
+                        retry_after_secs: SECS_PER_DAY - now % SECS_PER_DAY,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Quota check failed for {}, allowing request: {:#}", key, e),
+            }
+        }
+
+        Ok(response_headers)
+    }
+
+    /// Take a token from `key`'s bucket. A new caller past [`MAX_BUCKETS`]
+    /// evicts the least recently used bucket, so neither memory nor the
+    /// time spent under the lock grows with the number of callers.
+    fn take(&self, key: &str, policy: &RateLimitPolicy, now: Instant) -> Take {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.get_or_insert_mut(key.to_string(), || Bucket::full(policy, now));
+        // The route's limits changed on reload: start over under the new ones
+        if !bucket.has_policy(policy) {
+            *bucket = Bucket::full(policy, now);
+        }
+        bucket.take(now)
+    }
+}
+
+/// The caller identifier a limit is keyed by.
+///
+/// Only authenticated credentials are used: an API key counts once the
+/// gateway has verified it, so clients cannot pick their own bucket.
+fn caller_key(key: LimitKey, identity: Option<&Identity>, client: Option<IpAddr>) -> String {
+    let ip = || client.map_or_else(|| "ip:unknown".to_string(), |ip| format!("ip:{}", ip));
+    let subject = || identity.map_or_else(ip, |i| format!("sub:{}", i.subject));
+    match key {
+        LimitKey::Subject => subject(),
+        LimitKey::ApiKey => identity
+            .and_then(|i| i.api_key.as_ref())
+            .map_or_else(subject, |id| format!("key:{}", id)),
+        LimitKey::Ip => ip(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn policy(rate: u32, per_secs: u64, burst: Option<u32>) -> RateLimitPolicy {
+        RateLimitPolicy {
+            rate,
+            per_secs,
+            burst,
+            key: LimitKey::Subject,
+        }
+    }
+
+    fn route(rate_limit: Option<RateLimitPolicy>, daily_quota: Option<u64>) -> Route {
+        let mut route: Route = serde_yaml::from_str(
+            "{ name: worker, prefix: /api/v1/worker, upstream: 'http://agent-worker:8082' }",
+        )
+        .unwrap();
+        route.rate_limit = rate_limit;
+        route.daily_quota = daily_quota;
+        route
+    }
+
+    fn identity(subject: &str, tenant: &str) -> Identity {
+        Identity {
+            subject: subject.to_string(),
+            user: subject.to_string(),
+            tenant: Some(tenant.to_string()),
+            scopes: Vec::new(),
+            api_key: None,
+        }
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let policy = policy(60, 60, Some(2));
+        let start = Instant::now();
+        let mut bucket = Bucket::full(&policy, start);
+
+        assert_eq!(
+            bucket.take(start),
+            Take::Allowed {
+                remaining: 1,
+                reset_secs: 1
+            }
+        );
+        assert!(matches!(bucket.take(start), Take::Allowed { remaining: 0, .. }));
+        assert_eq!(bucket.take(start), Take::Limited { retry_after_secs: 1 });
+
+        let later = start + Duration::from_secs(1);
+        assert!(matches!(bucket.take(later), Take::Allowed { remaining: 0, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_limits_each_caller_separately() {
+        let limiter = RateLimiter::default();
+        let route = route(Some(policy(1, 60, None)), None);
+        let alice = identity("alice", "search");
+        let bob = identity("bob", "search");
+
+        let headers = limiter.check(&route, Some(&alice), None).await.unwrap();
+        assert_eq!(headers["ratelimit-limit"], "1");
+        assert_eq!(headers["ratelimit-remaining"], "0");
+        assert_eq!(headers["ratelimit-policy"], "1;w=60;burst=1");
+
+        let rejection = limiter.check(&route, Some(&alice), None).await.unwrap_err();
+        let response = rejection.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+
+        assert!(limiter.check(&route, Some(&bob), None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_daily_quota_per_tenant() {
+        let limiter = RateLimiter::default();
+        let route = route(None, Some(2));
+
+        // Shared by everyone in the tenant
+        assert!(limiter.check(&route, Some(&identity("alice", "search")), None).await.is_ok());
+        assert!(limiter.check(&route, Some(&identity("bob", "search")), None).await.is_ok());
+        let rejection = limiter
+            .check(&route, Some(&identity("carol", "search")), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(rejection, Rejection::QuotaExceeded { limit: 2, .. }));
+
+        assert!(limiter.check(&route, Some(&identity("dave", "ads")), None).await.is_ok());
+    }
+
+    #[test]
+    fn test_caller_keys() {
+        let ip: Option<IpAddr> = Some("10.0.0.4".parse().unwrap());
+        let alice = identity("alice", "search");
+        let ci = Identity {
+            api_key: Some("ci-deploy".to_string()),
+            ..identity("apikey:ci-deploy", "search")
+        };
+
+        assert_eq!(caller_key(LimitKey::Subject, Some(&alice), ip), "sub:alice");
+        assert_eq!(caller_key(LimitKey::Subject, None, ip), "ip:10.0.0.4");
+        assert_eq!(caller_key(LimitKey::ApiKey, Some(&ci), ip), "key:ci-deploy");
+        assert_eq!(caller_key(LimitKey::ApiKey, Some(&alice), ip), "sub:alice");
+        assert_eq!(caller_key(LimitKey::ApiKey, None, ip), "ip:10.0.0.4");
+        assert_eq!(caller_key(LimitKey::Ip, Some(&alice), ip), "ip:10.0.0.4");
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_buckets() {
+        let limiter = RateLimiter::with_max_buckets(Arc::new(InMemoryQuotaStore::default()), 2);
+        let slow = policy(1, 3600, None);
+        let now = Instant::now();
+
+        limiter.take("alice", &slow, now);
+        limiter.take("bob", &slow, now);
+        // Alice is used again, so a new caller evicts Bob
+        assert!(matches!(limiter.take("alice", &slow, now), Take::Limited { .. }));
+        limiter.take("carol", &slow, now);
+
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+        assert!(matches!(limiter.take("alice", &slow, now), Take::Limited { .. }));
+        assert!(matches!(limiter.take("bob", &slow, now), Take::Allowed { .. }));
+    }
+
+    #[test]
+    fn test_policy_change_rebuilds_bucket() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+
+        limiter.take("worker:alice", &policy(1, 3600, None), now);
+        assert!(matches!(limiter.take("worker:alice", &policy(1, 3600, None), now), Take::Limited { .. }));
+        // Reloaded with a larger limit, as the response headers now advertise
+        assert_eq!(
+            limiter.take("worker:alice", &policy(10, 60, None), now),
+            Take::Allowed {
+                remaining: 9,
+                reset_secs: 6
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quota_counters_reset_each_day() {
+        let store = InMemoryQuotaStore::default();
+
+        assert_eq!(store.increment("worker:search", 100).await.unwrap(), 1);
+        assert_eq!(store.increment("worker:search", 100).await.unwrap(), 2);
+        assert_eq!(store.increment("worker:ads", 100).await.unwrap(), 1);
+        assert_eq!(store.increment("worker:search", 101).await.unwrap(), 1);
+        assert_eq!(store.counters.lock().unwrap().counts.len(), 1);
+    }
+}
@@ -0,0 +1,488 @@
+//! Route Table
+//!
+//! Maps request path prefixes to upstream services. The table is loaded from
+//! a YAML or TOML file (`GATEWAY_ROUTES_FILE`) and reloaded when the file
+//! changes, so adding a backend is a config change rather than a redeploy.
+//! Without a file, the built-in `engine` and `worker` routes are used.
+//!
+//! ```yaml
+//! routes:
+//!   - name: engine
+//!     prefix: /api/v1/engine
+//!     upstream: http://engine:8080
+//!     scopes: [provision:read]
+//!   - name: billing
+//!     prefix: /api/v1/billing
+//!     upstream: http://billing:8090
+//!     rewrite: /v2
+//!     methods: [GET, POST]
+//!     timeout_secs: 10
+//!     retry: { attempts: 2, backoff_ms: 200 }
+//!     rate_limit: { rate: 60, per_secs: 60, burst: 10 }
+//!     daily_quota: 5000
+//! ```
+
+use anyhow::{Context, Result};
+use axum::http::{Method, StatusCode};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::ratelimit::RateLimitPolicy;
+use crate::upstream::{Balance, CircuitBreakerPolicy, HealthCheckPolicy};
+
+/// Seconds between checks of the route file when `GATEWAY_ROUTES_RELOAD_SECS` is unset.
+const DEFAULT_RELOAD_INTERVAL_SECS: u64 = 5;
+
+/// A route to an upstream service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    /// Service name, used in logs and metrics
+    pub name: String,
+    /// Path prefix this route serves, matched on segment boundaries
+    pub prefix: String,
+    /// Upstream base URL; shorthand for a single entry in `upstreams`
+    #[serde(default)]
+    pub upstream: Option<String>,
+    /// Upstream base URLs requests are balanced across
+    #[serde(default)]
+    pub upstreams: Vec<String>,
+    #[serde(default)]
+    pub balance: Balance,
+    /// Active checks of each upstream; unchecked upstreams are assumed healthy
+    #[serde(default)]
+    pub health_check: Option<HealthCheckPolicy>,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerPolicy,
+    /// Replacement for the matched prefix; by default the prefix is stripped
+    #[serde(default)]
+    pub rewrite: String,
+    /// Allowed methods; empty allows any
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// Time to wait for response headers, overriding `GATEWAY_UPSTREAM_TIMEOUT_SECS`
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Scopes the caller's token must carry
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Per-caller token bucket
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitPolicy>,
+    /// Requests per tenant per UTC day
+    #[serde(default)]
+    pub daily_quota: Option<u64>,
+}
+
+/// How failed upstream calls are retried
+///
+/// Only idempotent requests without a body are retried, since a streamed
+/// body cannot be replayed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    #[serde(default)]
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for each further one
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Upstream statuses that are retried, in addition to connection errors
+    #[serde(default = "default_retry_statuses")]
+    pub statuses: Vec<u16>,
+}
+
+fn default_backoff_ms() -> u64 {
+    100
+}
+
+fn default_retry_statuses() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 0,
+            backoff_ms: default_backoff_ms(),
+            statuses: default_retry_statuses(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (starting at 1).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.backoff_ms.saturating_mul(1 << (retry - 1).min(10)))
+    }
+
+    pub fn retries_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status.as_u16())
+    }
+}
+
+/// Whether `path` has `.` or `..` segments, plain or percent-encoded.
+///
+/// URL parsing collapses these when the upstream URL is built (treating `\`
+/// as `/`, as for any http URL), so such a path could match one route and
+/// reach another's upstream; the proxy rejects them rather than route them.
+pub fn has_dot_segments(path: &str) -> bool {
+    path.split(['/', '\\']).any(|segment| {
+        let segment = segment.to_ascii_lowercase().replace("%2e", ".");
+        segment == "." || segment == ".."
+    })
+}
+
+impl Route {
+    /// Whether the route serves `path`.
+    fn matches(&self, path: &str) -> bool {
+        let prefix = self.prefix.trim_end_matches('/');
+        match path.strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.is_empty(),
+            None => false,
+        }
+    }
+
+    pub fn allows(&self, method: &Method) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method.as_str()))
+    }
+
+    /// URL on the `upstream` base for `path` (which must match) and an optional query.
+    pub fn target_url(&self, upstream: &str, path: &str, query: Option<&str>) -> String {
+        let rest = path
+            .strip_prefix(self.prefix.trim_end_matches('/'))
+            .unwrap_or(path);
+        let mut upstream_path = format!("{}{}", self.rewrite.trim_end_matches('/'), rest);
+        if !upstream_path.starts_with('/') {
+            upstream_path.insert(0, '/');
+        }
+
+        let mut url = format!("{}{}", upstream.trim_end_matches('/'), upstream_path);
+        if let Some(query) = query {
+            url.push('?');
+            url.push_str(query);
+        }
+        url
+    }
+}
+
+/// The set of routes, matched longest prefix first
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteTable {
+    routes: Vec<Route>,
+}
+
+impl RouteTable {
+    pub fn new(mut routes: Vec<Route>) -> Result<Self> {
+        for route in &mut routes {
+            route.upstreams.extend(route.upstream.take());
+            if route.upstreams.is_empty() {
+                anyhow::bail!("route {:?}: no upstream configured", route.name);
+            }
+            if !route.prefix.starts_with('/') {
+                anyhow::bail!("route {:?}: prefix must start with '/'", route.name);
+            }
+            if route.rate_limit.as_ref().is_some_and(|limit| limit.rate == 0) {
+                anyhow::bail!("route {:?}: rate_limit.rate must be positive", route.name);
+            }
+            for upstream in &route.upstreams {
+                reqwest::Url::parse(upstream)
+                    .with_context(|| format!("route {:?}: invalid upstream {:?}", route.name, upstream))?;
+            }
+            for method in &route.methods {
+                Method::from_bytes(method.as_bytes())
+                    .with_context(|| format!("route {:?}: invalid method {:?}", route.name, method))?;
+            }
+        }
+
+        routes.sort_by_key(|r| std::cmp::Reverse(r.prefix.trim_end_matches('/').len()));
+        Ok(Self { routes })
+    }
+
+    /// Built-in routes: `/api/v1/engine` and `/api/v1/worker`, with upstreams
+    /// from `ENGINE_URL` and `WORKER_URL` (comma-separated for several).
+    pub fn from_env_defaults() -> Result<Self> {
+        let route = |name: &str, upstreams: String| Route {
+            name: name.to_string(),
+            prefix: format!("/api/v1/{}", name),
+            upstream: None,
+            upstreams: upstreams.split(',').map(|u| u.trim().to_string()).collect(),
+            balance: Balance::default(),
+            health_check: Some(HealthCheckPolicy::default()),
+            circuit_breaker: CircuitBreakerPolicy::default(),
+            rewrite: String::new(),
+            methods: Vec::new(),
+            timeout_secs: None,
+            retry: RetryPolicy::default(),
+            scopes: Vec::new(),
+            rate_limit: None,
+            daily_quota: None,
+        };
+
+        Self::new(vec![
+            route(
+                "engine",
+                std::env::var("ENGINE_URL").unwrap_or_else(|_| "http://engine:8080".to_string()),
+            ),
+            route(
+                "worker",
+                std::env::var("WORKER_URL").unwrap_or_else(|_| "http://agent-worker:8082".to_string()),
+            ),
+        ])
+    }
+
+    /// Parse a route file; `.toml` files are read as TOML, anything else as YAML.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let table: RouteTable = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&raw).with_context(|| format!("Invalid route file {}", path.display()))?,
+            _ => serde_yaml::from_str(&raw).with_context(|| format!("Invalid route file {}", path.display()))?,
+        };
+        Self::new(table.routes)
+    }
+
+    /// The route serving `path`, preferring the longest prefix.
+    pub fn resolve(&self, path: &str) -> Option<&Route> {
+        self.routes.iter().find(|r| r.matches(path))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Route> {
+        self.routes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+}
+
+/// The live route table, swapped atomically on reload
+#[derive(Clone)]
+pub struct Routes {
+    table: Arc<RwLock<Arc<RouteTable>>>,
+}
+
+impl Routes {
+    pub fn new(table: RouteTable) -> Self {
+        Self {
+            table: Arc::new(RwLock::new(Arc::new(table))),
+        }
+    }
+
+    /// Snapshot of the current table; a reload does not affect requests
+    /// already holding one.
+    pub fn current(&self) -> Arc<RouteTable> {
+        self.table.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn replace(&self, table: RouteTable) {
+        *self.table.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(table);
+    }
+
+    /// Load routes from `GATEWAY_ROUTES_FILE`, reloading it every
+    /// `GATEWAY_ROUTES_RELOAD_SECS` when it changes, or fall back to the
+    /// built-in routes.
+    pub fn from_env() -> Result<Self> {
+        let Some(path) = std::env::var("GATEWAY_ROUTES_FILE").ok().filter(|p| !p.is_empty()) else {
+            return Ok(Self::new(RouteTable::from_env_defaults()?));
+        };
+
+        let path = PathBuf::from(path);
+        let table = RouteTable::load(&path)?;
+        info!("Loaded {} routes from {}", table.len(), path.display());
+
+        let routes = Self::new(table);
+        let interval = std::env::var("GATEWAY_ROUTES_RELOAD_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(DEFAULT_RELOAD_INTERVAL_SECS));
+        tokio::spawn(routes.clone().watch(path, interval));
+
+        Ok(routes)
+    }
+
+    /// Poll `path` and swap in the new table whenever its modification time
+    /// changes. An invalid file is logged and the previous table kept.
+    async fn watch(self, path: PathBuf, interval: Duration) {
+        let mut last_modified = modified(&path);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match RouteTable::load(&path) {
+                Ok(table) => {
+                    info!("Reloaded {} routes from {}", table.len(), path.display());
+                    self.replace(table);
+                }
+                Err(e) => warn!("Keeping previous routes: {:#}", e),
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = r#"
+routes:
+  - name: engine
+    prefix: /api/v1/engine
+    upstream: http://engine:8080
+    scopes: [provision:read]
+  - name: engine-dns
+    prefix: /api/v1/engine/api/dns
+    upstreams: [http://engine:8080/, http://engine-2:8080]
+    balance: least_connections
+    rewrite: /api/dns
+    methods: [get]
+    timeout_secs: 5
+    retry: { attempts: 2 }
+"#;
+
+    fn table() -> RouteTable {
+        RouteTable::new(serde_yaml::from_str::<RouteTable>(YAML).unwrap().routes).unwrap()
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let table = table();
+
+        assert_eq!(table.resolve("/api/v1/engine/api/dns/list").unwrap().name, "engine-dns");
+        assert_eq!(table.resolve("/api/v1/engine/api/agents/status").unwrap().name, "engine");
+        assert_eq!(table.resolve("/api/v1/engine").unwrap().name, "engine");
+        assert!(table.resolve("/api/v1/engineering").is_none());
+        assert!(table.resolve("/api/v1/worker/tasks").is_none());
+    }
+
+    #[test]
+    fn test_dot_segments() {
+        for path in [
+            "/api/v1/engine/x/../api/dns/create",
+            "/api/v1/engine/x/%2e%2E/api/dns/create",
+            "/api/v1/engine/x/.%2e/api",
+            "/api/v1/engine/x\\..\\api",
+            "/api/v1/engine/./health",
+            "/api/v1/engine/..",
+        ] {
+            assert!(has_dot_segments(path), "{}", path);
+        }
+        for path in ["/api/v1/engine/health", "/api/v1/engine/v1.2/...", "/api/v1/engine/.well-known/x"] {
+            assert!(!has_dot_segments(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_target_url_rewrites_prefix() {
+        let table = table();
+
+        let engine = table.resolve("/api/v1/engine/health").unwrap();
+        let upstream = &engine.upstreams[0];
+        assert_eq!(
+            engine.target_url(upstream, "/api/v1/engine/health", None),
+            "http://engine:8080/health"
+        );
+        assert_eq!(engine.target_url(upstream, "/api/v1/engine", None), "http://engine:8080/");
+
+        let dns = table.resolve("/api/v1/engine/api/dns/list").unwrap();
+        assert_eq!(
+            dns.target_url(&dns.upstreams[0], "/api/v1/engine/api/dns/list", Some("zone=lornu.ai")),
+            "http://engine:8080/api/dns/list?zone=lornu.ai"
+        );
+    }
+
+    #[test]
+    fn test_route_policy() {
+        let table = table();
+        let dns = table.resolve("/api/v1/engine/api/dns/list").unwrap();
+
+        assert!(dns.allows(&Method::GET));
+        assert!(!dns.allows(&Method::POST));
+        assert_eq!(dns.timeout_secs, Some(5));
+        assert_eq!(dns.retry.attempts, 2);
+        assert!(dns.retry.retries_status(StatusCode::BAD_GATEWAY));
+        assert_eq!(dns.retry.backoff(2), Duration::from_millis(200));
+
+        assert_eq!(dns.upstreams.len(), 2);
+        assert_eq!(dns.balance, Balance::LeastConnections);
+
+        let engine = table.resolve("/api/v1/engine/health").unwrap();
+        assert_eq!(engine.upstreams, vec!["http://engine:8080"]);
+        assert!(engine.allows(&Method::DELETE));
+        assert_eq!(engine.scopes, vec!["provision:read"]);
+    }
+
+    #[test]
+    fn test_toml_and_validation() {
+        let toml = r#"
+[[routes]]
+name = "worker"
+prefix = "/api/v1/worker"
+upstream = "http://agent-worker:8082"
+"#;
+        let path = std::env::temp_dir().join(format!("lornu-routes-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, toml).unwrap();
+        let table = RouteTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(table.resolve("/api/v1/worker/tasks").unwrap().name, "worker");
+
+        let invalid: RouteTable = serde_yaml::from_str(
+            "routes:\n  - { name: bad, prefix: api, upstream: 'http://bad' }\n",
+        )
+        .unwrap();
+        assert!(RouteTable::new(invalid.routes).is_err());
+
+        let missing: RouteTable = serde_yaml::from_str("routes:\n  - { name: bad, prefix: /api }\n").unwrap();
+        assert!(RouteTable::new(missing.routes).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reload_on_change() {
+        let path = std::env::temp_dir().join(format!("lornu-routes-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, YAML).unwrap();
+
+        let routes = Routes::new(RouteTable::load(&path).unwrap());
+        tokio::spawn(routes.clone().watch(path.clone(), Duration::from_millis(20)));
+
+        // Make sure the new file gets a different modification time
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(
+            &path,
+            "routes:\n  - { name: billing, prefix: /api/v1/billing, upstream: 'http://billing:8090' }\n",
+        )
+        .unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1)).unwrap();
+
+        let mut reloaded = false;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if routes.current().resolve("/api/v1/billing/invoices").is_some() {
+                reloaded = true;
+                break;
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(reloaded, "route table was not reloaded");
+        assert!(routes.current().resolve("/api/v1/engine/health").is_none());
+    }
+}
@@ -0,0 +1,479 @@
+//! Upstream Targets
+//!
+//! Load balancing across a route's upstream targets, with active health
+//! checks and a per-target circuit breaker. A target is skipped while its
+//! health check fails or its breaker is open; when no target is usable the
+//! proxy fails fast instead of waiting on a dead backend.
+//!
+//! The breaker opens after `failures` consecutive errors (connection errors,
+//! timeouts, 502/503/504) and stays open for `open_secs`. It then lets a single
+//! trial request through: success closes it, failure opens it again.
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::routes::{Route, Routes};
+
+/// How often the health checker looks for targets that are due a check.
+const HEALTH_CHECK_TICK: Duration = Duration::from_secs(1);
+
+/// How a target is chosen among the usable ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    #[default]
+    RoundRobin,
+    /// Fewest requests in flight, including streaming responses
+    LeastConnections,
+}
+
+/// Active health check settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheckPolicy {
+    #[serde(default = "default_health_path")]
+    pub path: String,
+    #[serde(default = "default_health_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_health_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Consecutive failed checks before a target is marked down
+    #[serde(default = "default_health_threshold")]
+    pub unhealthy_threshold: u32,
+    /// Consecutive passed checks before a down target is used again
+    #[serde(default = "default_health_threshold")]
+    pub healthy_threshold: u32,
+}
+
+fn default_health_path() -> String {
+    "/health".to_string()
+}
+
+fn default_health_interval_secs() -> u64 {
+    10
+}
+
+fn default_health_timeout_secs() -> u64 {
+    2
+}
+
+fn default_health_threshold() -> u32 {
+    2
+}
+
+impl Default for HealthCheckPolicy {
+    fn default() -> Self {
+        Self {
+            path: default_health_path(),
+            interval_secs: default_health_interval_secs(),
+            timeout_secs: default_health_timeout_secs(),
+            unhealthy_threshold: default_health_threshold(),
+            healthy_threshold: default_health_threshold(),
+        }
+    }
+}
+
+/// Circuit breaker settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerPolicy {
+    /// Consecutive failed requests that open the breaker
+    #[serde(default = "default_breaker_failures")]
+    pub failures: u32,
+    /// Seconds the breaker stays open before a trial request
+    #[serde(default = "default_breaker_open_secs")]
+    pub open_secs: u64,
+}
+
+fn default_breaker_failures() -> u32 {
+    5
+}
+
+fn default_breaker_open_secs() -> u64 {
+    30
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failures: default_breaker_failures(),
+            open_secs: default_breaker_open_secs(),
+        }
+    }
+}
+
+/// Whether an upstream response counts against the target.
+pub fn is_failure_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+impl Breaker {
+    /// Whether a request may be sent; claims the trial slot when half-open.
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        match self.open_until {
+            None => true,
+            Some(until) if now < until => false,
+            Some(_) if self.trial_in_flight => false,
+            Some(_) => {
+                self.trial_in_flight = true;
+                true
+            }
+        }
+    }
+
+    /// Record a request outcome; returns true when this opened the breaker.
+    fn record(&mut self, success: bool, policy: &CircuitBreakerPolicy, now: Instant) -> bool {
+        if success {
+            *self = Self::default();
+            return false;
+        }
+
+        self.failures += 1;
+        if self.trial_in_flight || self.failures >= policy.failures.max(1) {
+            self.open_until = Some(now + Duration::from_secs(policy.open_secs));
+            self.trial_in_flight = false;
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Debug)]
+struct Health {
+    healthy: bool,
+    passed: u32,
+    failed: u32,
+    next_check: Instant,
+}
+
+/// Runtime state of one upstream target, shared by every route using it
+#[derive(Debug)]
+pub struct Target {
+    url: String,
+    in_flight: AtomicUsize,
+    breaker: Mutex<Breaker>,
+    health: Mutex<Health>,
+}
+
+impl Target {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            in_flight: AtomicUsize::new(0),
+            breaker: Mutex::new(Breaker::default()),
+            health: Mutex::new(Health {
+                healthy: true,
+                passed: 0,
+                failed: 0,
+                next_check: Instant::now(),
+            }),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.health.lock().unwrap_or_else(|e| e.into_inner()).healthy
+    }
+
+    /// Record a health check result.
+    fn record_check(&self, passed: bool, policy: &HealthCheckPolicy) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        health.next_check = Instant::now() + Duration::from_secs(policy.interval_secs);
+
+        if passed {
+            health.passed += 1;
+            health.failed = 0;
+            if !health.healthy && health.passed >= policy.healthy_threshold {
+                info!("Upstream {} is healthy again", self.url);
+                health.healthy = true;
+            }
+        } else {
+            health.failed += 1;
+            health.passed = 0;
+            if health.healthy && health.failed >= policy.unhealthy_threshold {
+                warn!("Upstream {} failed {} health checks, marking down", self.url, health.failed);
+                health.healthy = false;
+            }
+        }
+    }
+
+    /// Claim the target for a request if it is healthy and its breaker allows.
+    fn try_acquire(&self, now: Instant) -> bool {
+        self.is_healthy() && self.breaker.lock().unwrap_or_else(|e| e.into_inner()).try_acquire(now)
+    }
+}
+
+/// A target chosen for one request; holds an in-flight slot until dropped
+#[derive(Debug)]
+pub struct Selected {
+    target: Arc<Target>,
+    breaker: CircuitBreakerPolicy,
+    reported: bool,
+}
+
+impl Selected {
+    pub fn url(&self) -> &str {
+        &self.target.url
+    }
+
+    /// Record whether the request succeeded, for the circuit breaker.
+    pub fn report(&mut self, success: bool) {
+        self.reported = true;
+        let opened = self
+            .target
+            .breaker
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(success, &self.breaker, Instant::now());
+        if opened {
+            warn!(
+                "Circuit opened for upstream {} for {}s",
+                self.target.url, self.breaker.open_secs
+            );
+        }
+    }
+}
+
+impl Drop for Selected {
+    fn drop(&mut self) {
+        self.target.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if !self.reported {
+            // Abandoned before an outcome (e.g. client went away): free the trial slot
+            self.target.breaker.lock().unwrap_or_else(|e| e.into_inner()).trial_in_flight = false;
+        }
+    }
+}
+
+/// Target state for every upstream in the route table
+#[derive(Default)]
+pub struct Upstreams {
+    targets: Mutex<HashMap<String, Arc<Target>>>,
+    cursors: Mutex<HashMap<String, usize>>,
+}
+
+impl Upstreams {
+    fn target(&self, url: &str) -> Arc<Target> {
+        self.targets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(url.to_string())
+            .or_insert_with(|| Arc::new(Target::new(url)))
+            .clone()
+    }
+
+    /// Choose a target for `route`, or `None` when every target is down.
+    pub fn select(&self, route: &Route) -> Option<Selected> {
+        let targets: Vec<Arc<Target>> = route.upstreams.iter().map(|url| self.target(url)).collect();
+        let now = Instant::now();
+
+        let order: Vec<usize> = match route.balance {
+            Balance::RoundRobin => {
+                let mut cursors = self.cursors.lock().unwrap_or_else(|e| e.into_inner());
+                let cursor = cursors.entry(route.name.clone()).or_default();
+                let start = *cursor % targets.len().max(1);
+                *cursor = cursor.wrapping_add(1);
+                (0..targets.len()).map(|i| (start + i) % targets.len()).collect()
+            }
+            Balance::LeastConnections => {
+                let mut order: Vec<usize> = (0..targets.len()).collect();
+                order.sort_by_key(|&i| targets[i].in_flight.load(Ordering::Relaxed));
+                order
+            }
+        };
+
+        let target = order
+            .into_iter()
+            .map(|i| &targets[i])
+            .find(|target| target.try_acquire(now))?
+            .clone();
+        target.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        Some(Selected {
+            target,
+            breaker: route.circuit_breaker.clone(),
+            reported: false,
+        })
+    }
+
+    /// Health-check the targets of every route that configures a check,
+    /// picking up route table reloads as they happen.
+    pub async fn run_health_checks(self: Arc<Self>, routes: Routes, client: reqwest::Client) {
+        let mut ticker = tokio::time::interval(HEALTH_CHECK_TICK);
+        loop {
+            ticker.tick().await;
+
+            let table = routes.current();
+            let now = Instant::now();
+            for route in table.iter() {
+                let Some(policy) = &route.health_check else {
+                    continue;
+                };
+
+                for url in &route.upstreams {
+                    let target = self.target(url);
+                    {
+                        let mut health = target.health.lock().unwrap_or_else(|e| e.into_inner());
+                        if health.next_check > now {
+                            continue;
+                        }
+                        // Not checked again until this one finishes or times out
+                        health.next_check = now + Duration::from_secs(policy.interval_secs + policy.timeout_secs);
+                    }
+
+                    let client = client.clone();
+                    let policy = policy.clone();
+                    tokio::spawn(async move {
+                        let passed = check(&client, &target.url, &policy).await;
+                        target.record_check(passed, &policy);
+                    });
+                }
+            }
+        }
+    }
+}
+
+async fn check(client: &reqwest::Client, url: &str, policy: &HealthCheckPolicy) -> bool {
+    let url = format!("{}{}", url.trim_end_matches('/'), policy.path);
+    match client
+        .get(&url)
+        .timeout(Duration::from_secs(policy.timeout_secs))
+        .send()
+        .await
+    {
+        Ok(resp) => resp.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(upstreams: &[&str], balance: Balance) -> Route {
+        let mut route: Route = serde_yaml::from_str("{ name: worker, prefix: /api/v1/worker }").unwrap();
+        route.upstreams = upstreams.iter().map(|u| u.to_string()).collect();
+        route.balance = balance;
+        route.circuit_breaker = CircuitBreakerPolicy {
+            failures: 2,
+            open_secs: 30,
+        };
+        route
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let upstreams = Upstreams::default();
+        let route = route(&["http://w1", "http://w2"], Balance::RoundRobin);
+
+        let picked: Vec<String> = (0..4)
+            .map(|_| upstreams.select(&route).unwrap().url().to_string())
+            .collect();
+        assert_eq!(picked, ["http://w1", "http://w2", "http://w1", "http://w2"]);
+    }
+
+    #[test]
+    fn test_least_connections() {
+        let upstreams = Upstreams::default();
+        let route = route(&["http://w1", "http://w2"], Balance::LeastConnections);
+
+        let first = upstreams.select(&route).unwrap();
+        assert_eq!(first.url(), "http://w1");
+        // w1 is busy with a (streaming) request
+        assert_eq!(upstreams.select(&route).unwrap().url(), "http://w2");
+
+        drop(first);
+        assert_eq!(upstreams.select(&route).unwrap().url(), "http://w1");
+    }
+
+    #[test]
+    fn test_breaker_ejects_failing_target() {
+        let upstreams = Upstreams::default();
+        let route = route(&["http://w1", "http://w2"], Balance::RoundRobin);
+
+        for _ in 0..2 {
+            let mut selected = upstreams.select(&route).unwrap();
+            assert_eq!(selected.url(), "http://w1");
+            selected.report(false);
+            upstreams.select(&route).unwrap().report(true);
+        }
+
+        // w1 is open, so every request goes to w2
+        for _ in 0..3 {
+            assert_eq!(upstreams.select(&route).unwrap().url(), "http://w2");
+        }
+    }
+
+    #[test]
+    fn test_breaker_half_open_trial() {
+        let policy = CircuitBreakerPolicy {
+            failures: 1,
+            open_secs: 10,
+        };
+        let start = Instant::now();
+        let mut breaker = Breaker::default();
+
+        assert!(breaker.try_acquire(start));
+        assert!(breaker.record(false, &policy, start));
+        assert!(!breaker.try_acquire(start + Duration::from_secs(5)));
+
+        // One trial after the open period; a failure re-opens
+        let later = start + Duration::from_secs(11);
+        assert!(breaker.try_acquire(later));
+        assert!(!breaker.try_acquire(later));
+        assert!(breaker.record(false, &policy, later));
+        assert!(!breaker.try_acquire(later));
+
+        let much_later = later + Duration::from_secs(11);
+        assert!(breaker.try_acquire(much_later));
+        breaker.record(true, &policy, much_later);
+        assert!(breaker.try_acquire(much_later));
+        assert!(breaker.try_acquire(much_later));
+    }
+
+    #[test]
+    fn test_all_targets_down() {
+        let upstreams = Upstreams::default();
+        let route = route(&["http://w1"], Balance::RoundRobin);
+        let policy = HealthCheckPolicy {
+            unhealthy_threshold: 1,
+            ..HealthCheckPolicy::default()
+        };
+
+        upstreams.target("http://w1").record_check(false, &policy);
+        assert!(upstreams.select(&route).is_none());
+
+        upstreams.target("http://w1").record_check(true, &policy);
+        assert!(upstreams.select(&route).is_none(), "needs two passing checks");
+        upstreams.target("http://w1").record_check(true, &policy);
+        assert!(upstreams.select(&route).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_health_checks_hit_health_endpoint() {
+        use axum::{routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let healthy = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new().route("/health", get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let policy = HealthCheckPolicy::default();
+        assert!(check(&client, &healthy, &policy).await);
+        assert!(!check(&client, "http://127.0.0.1:9", &policy).await);
+    }
+}
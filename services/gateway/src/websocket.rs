@@ -0,0 +1,242 @@
+//! WebSocket Tunnelling
+//!
+//! `Upgrade: websocket` requests are forwarded with their handshake headers
+//! intact. Once the upstream answers `101 Switching Protocols`, the client
+//! and upstream connections are spliced together byte for byte, so frames,
+//! subprotocols and extensions pass through unchanged. A tunnel with no
+//! traffic in either direction for the idle timeout is closed.
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::Response,
+};
+use hyper_util::rt::TokioIo;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::proxy::error_response;
+use crate::upstream::Selected;
+
+/// Default idle timeout for tunnels (`GATEWAY_WS_IDLE_TIMEOUT_SECS`).
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Whether the request asks to upgrade to a WebSocket.
+pub fn is_upgrade(headers: &HeaderMap) -> bool {
+    let upgrade = headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    let connection = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    upgrade && connection
+}
+
+/// Restore the upgrade headers that hop-by-hop filtering removed.
+pub fn upgrade_headers(headers: &mut HeaderMap) {
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+}
+
+/// Complete the handshake with the upstream and tunnel the connection.
+///
+/// `selected` is held for the life of the tunnel so the target counts as busy
+/// for least-connections balancing.
+pub async fn tunnel(
+    client: &reqwest::Client,
+    target_url: &str,
+    mut request: Request<Body>,
+    upstream_headers: HeaderMap,
+    mut selected: Selected,
+    handshake_timeout: Duration,
+    idle_timeout: Duration,
+) -> Response {
+    let client_upgrade = hyper::upgrade::on(&mut request);
+
+    let handshake = client
+        .get(target_url)
+        .version(reqwest::Version::HTTP_11)
+        .headers(upstream_headers)
+        .send();
+    let upstream = match tokio::time::timeout(handshake_timeout, handshake).await {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(e)) => {
+            selected.report(false);
+            warn!("WebSocket handshake with {} failed: {}", target_url, e);
+            return error_response(StatusCode::BAD_GATEWAY, "upstream_unreachable");
+        }
+        Err(_) => {
+            selected.report(false);
+            warn!("WebSocket handshake with {} timed out", target_url);
+            return error_response(StatusCode::GATEWAY_TIMEOUT, "upstream_timeout");
+        }
+    };
+    selected.report(!crate::upstream::is_failure_status(upstream.status()));
+
+    // Anything but 101 (e.g. 401, 404) is relayed to the client as is
+    if upstream.status() != StatusCode::SWITCHING_PROTOCOLS {
+        let status = upstream.status();
+        let headers = crate::headers::end_to_end(upstream.headers());
+        let body = upstream.bytes().await.unwrap_or_default();
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        return response;
+    }
+
+    // The 101 is passed through, including Sec-WebSocket-Accept/Protocol/Extensions
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    for (name, value) in upstream.headers() {
+        response.headers_mut().append(name.clone(), value.clone());
+    }
+
+    let target_url = target_url.to_string();
+    tokio::spawn(async move {
+        let upstream_io = match upstream.upgrade().await {
+            Ok(io) => io,
+            Err(e) => {
+                warn!("WebSocket upgrade of {} failed: {}", target_url, e);
+                return;
+            }
+        };
+        let client_io = match client_upgrade.await {
+            Ok(io) => TokioIo::new(io),
+            Err(e) => {
+                warn!("WebSocket upgrade from client failed: {}", e);
+                return;
+            }
+        };
+
+        info!("WebSocket tunnel to {} opened", target_url);
+        match splice(client_io, upstream_io, idle_timeout).await {
+            Ok(Closed::Finished) => info!("WebSocket tunnel to {} closed", target_url),
+            Ok(Closed::Idle) => info!(
+                "WebSocket tunnel to {} closed after {}s idle",
+                target_url,
+                idle_timeout.as_secs()
+            ),
+            Err(e) => warn!("WebSocket tunnel to {} failed: {}", target_url, e),
+        }
+        drop(selected);
+    });
+
+    response
+}
+
+#[derive(Debug, PartialEq)]
+enum Closed {
+    /// Both sides closed their connection
+    Finished,
+    /// No traffic in either direction for the idle timeout
+    Idle,
+}
+
+/// Copy bytes both ways until both sides close or the tunnel goes idle.
+async fn splice<A, B>(a: A, b: B, idle_timeout: Duration) -> std::io::Result<Closed>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut a_read, mut a_write) = tokio::io::split(a);
+    let (mut b_read, mut b_write) = tokio::io::split(b);
+    let last_activity = Mutex::new(Instant::now());
+
+    let copy = async {
+        tokio::try_join!(
+            pump(&mut a_read, &mut b_write, &last_activity),
+            pump(&mut b_read, &mut a_write, &last_activity),
+        )
+    };
+    let idle = async {
+        loop {
+            let last = *last_activity.lock().unwrap_or_else(|e| e.into_inner());
+            if last.elapsed() >= idle_timeout {
+                return;
+            }
+            tokio::time::sleep_until((last + idle_timeout).into()).await;
+        }
+    };
+
+    tokio::select! {
+        result = copy => result.map(|_| Closed::Finished),
+        _ = idle => Ok(Closed::Idle),
+    }
+}
+
+/// Copy one direction, half-closing the writer when the reader finishes.
+async fn pump<R, W>(reader: &mut R, writer: &mut W, last_activity: &Mutex<Instant>) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            writer.shutdown().await?;
+            return Ok(());
+        }
+        writer.write_all(&buf[..n]).await?;
+        writer.flush().await?;
+        *last_activity.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_upgrade() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::UPGRADE, HeaderValue::from_static("WebSocket"));
+        assert!(!is_upgrade(&headers));
+
+        headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive, Upgrade"));
+        assert!(is_upgrade(&headers));
+
+        headers.insert(header::UPGRADE, HeaderValue::from_static("h2c"));
+        assert!(!is_upgrade(&headers));
+    }
+
+    #[tokio::test]
+    async fn test_splice_copies_both_ways() {
+        let (client, gateway_client_side) = tokio::io::duplex(64);
+        let (gateway_upstream_side, upstream) = tokio::io::duplex(64);
+        let tunnel = tokio::spawn(splice(gateway_client_side, gateway_upstream_side, Duration::from_secs(5)));
+
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+        let (mut upstream_read, mut upstream_write) = tokio::io::split(upstream);
+
+        client_write.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        upstream_read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        upstream_write.write_all(b"pong").await.unwrap();
+        client_read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        client_write.shutdown().await.unwrap();
+        upstream_write.shutdown().await.unwrap();
+        assert_eq!(tunnel.await.unwrap().unwrap(), Closed::Finished);
+    }
+
+    #[tokio::test]
+    async fn test_splice_closes_when_idle() {
+        let (_client, gateway_client_side) = tokio::io::duplex(64);
+        let (gateway_upstream_side, _upstream) = tokio::io::duplex(64);
+
+        let closed = splice(gateway_client_side, gateway_upstream_side, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(closed, Closed::Idle);
+    }
+}
@@ -0,0 +1,129 @@
+//! HTTP Metrics
+//!
+//! The request metrics every service exposes at `/metrics`, kept in one place
+//! so route labels and latency buckets match across the engine, gateway and
+//! agent worker. Each service adds its own series on top.
+
+use anyhow::Result;
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Instant;
+
+/// Buckets (seconds) for HTTP and API call latencies.
+pub const LATENCY_BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Route name set on a response, used as the `route` label in place of the
+/// matched path (e.g. every request the gateway proxies matches the same
+/// fallback).
+#[derive(Debug, Clone)]
+pub struct RouteLabel(pub String);
+
+/// Install the global Prometheus recorder.
+///
+/// `buckets` gives histograms that need their own buckets, by full metric
+/// name; every other `*_duration_seconds` histogram uses [`LATENCY_BUCKETS`].
+pub fn install(buckets: &[(&str, &[f64])]) -> Result<PrometheusHandle> {
+    let mut builder = PrometheusBuilder::new();
+    for (name, values) in buckets {
+        builder = builder.set_buckets_for_metric(Matcher::Full(name.to_string()), values)?;
+    }
+    let handle = builder
+        .set_buckets_for_metric(Matcher::Suffix("_duration_seconds".to_string()), &LATENCY_BUCKETS)?
+        .install_recorder()?;
+    Ok(handle)
+}
+
+/// Middleware: count and time requests by method, route and status.
+pub async fn track_requests(req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    let route = response
+        .extensions()
+        .get::<RouteLabel>()
+        .map(|label| label.0.clone())
+        .unwrap_or(route);
+    let status = response.status().as_u16().to_string();
+    metrics::counter!("http_requests_total", "method" => method.clone(), "route" => route.clone(), "status" => status)
+        .increment(1);
+    metrics::histogram!("http_request_duration_seconds", "method" => method, "route" => route)
+        .record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, response::IntoResponse, routing::get, Router};
+    use std::sync::OnceLock;
+    use tower::ServiceExt;
+
+    fn handle() -> &'static PrometheusHandle {
+        static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+        HANDLE.get_or_init(|| install(&[("slow_duration_seconds", &[60.0, 600.0])]).unwrap())
+    }
+
+    async fn get_uri(app: Router, uri: &str) {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requests_recorded_by_matched_route() {
+        let handle = handle();
+        let app: Router = Router::new()
+            .route("/api/provision/jobs/:id", get(|| async { StatusCode::NOT_FOUND }))
+            .layer(middleware::from_fn(track_requests));
+
+        get_uri(app, "/api/provision/jobs/1234").await;
+
+        let rendered = handle.render();
+        assert!(rendered.contains(
+            r#"http_requests_total{method="GET",route="/api/provision/jobs/:id",status="404"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"http_request_duration_seconds_bucket{method="GET",route="/api/provision/jobs/:id",le="30"} 1"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_route_label_overrides_matched_route() {
+        let handle = handle();
+        let labelled = || async {
+            let mut response = "ok".into_response();
+            response.extensions_mut().insert(RouteLabel("engine".to_string()));
+            response
+        };
+        let app: Router = Router::new()
+            .route("/api/v1/*path", get(labelled))
+            .layer(middleware::from_fn(track_requests));
+
+        get_uri(app, "/api/v1/engine/health").await;
+
+        assert!(handle
+            .render()
+            .contains(r#"http_requests_total{method="GET",route="engine",status="200"} 1"#));
+    }
+
+    #[test]
+    fn test_named_buckets() {
+        let handle = handle();
+        metrics::histogram!("slow_duration_seconds").record(90.0);
+
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"slow_duration_seconds_bucket{le="600"} 1"#));
+        assert!(!rendered.contains(r#"slow_duration_seconds_bucket{le="0.005"}"#));
+    }
+}